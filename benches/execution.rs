@@ -1,4 +1,5 @@
 use composer::blob;
+use composer::workload::{Distribution, Workload};
 use criterion::{criterion_group, criterion_main, Criterion};
 use ewasm::{Execute, Runtime};
 
@@ -6,7 +7,7 @@ static SHETH_BINARY: &'static [u8] =
     include_bytes!("../target/wasm32-unknown-unknown/release/sheth.wasm");
 
 fn large_proof(c: &mut Criterion) {
-    let (blob, pre_state, _) = blob::generate_with_roots(2, 1, 256);
+    let (blob, pre_state, _) = blob::generate_with_roots(2, 1, 256).unwrap();
     let blob = blob.to_bytes();
 
     c.bench_function("execute(2, 1, 256)", |b| {
@@ -17,5 +18,37 @@ fn large_proof(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, large_proof);
+fn skewed_workload(c: &mut Criterion) {
+    let workloads = vec![
+        ("uniform", Distribution::Uniform),
+        ("zipf:1.5", Distribution::Zipf(1.5)),
+        (
+            "hot:2:0.9",
+            Distribution::Hot {
+                accounts: 2,
+                rate: 0.9,
+            },
+        ),
+    ];
+
+    for (name, distribution) in workloads {
+        let workload = Workload {
+            distribution,
+            ..Workload::default()
+        };
+
+        let blob = blob::generate_with_workload(10, 50, 32, &workload).unwrap();
+        let (pre_state, _) = blob::roots(&blob, 32);
+        let blob = blob.to_bytes();
+
+        c.bench_function(&format!("execute(10, 50, 32, {})", name), |b| {
+            b.iter(|| {
+                let mut runtime = Runtime::new(SHETH_BINARY, &blob, pre_state);
+                runtime.execute();
+            })
+        });
+    }
+}
+
+criterion_group!(benches, large_proof, skewed_workload);
 criterion_main!(benches);
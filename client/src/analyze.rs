@@ -0,0 +1,29 @@
+use crate::inspect::read_blob;
+use composer::analyze::analyze;
+
+/// Measures the blob which is either hex encoded in `input` or stored in the file at `input`, and
+/// returns the report as JSON.
+pub fn build(input: &str, height: usize) -> Result<String, String> {
+    let blob = read_blob(input)?;
+    let report = analyze(&blob, height).map_err(|e| format!("Invalid blob: {:?}", e))?;
+
+    Ok(report.to_json())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use composer::blob;
+
+    #[test]
+    fn analyze_package_output() {
+        let blob = hex::encode(blob::generate(2, 1, 8).unwrap().to_bytes());
+
+        let output = build(&blob, 8).unwrap();
+        assert!(output.starts_with("{"));
+        assert!(output.contains("\"transactions\": 1"));
+        assert!(output.contains("\"rejected\": null"));
+
+        assert!(build(&blob[..10], 8).is_err());
+    }
+}
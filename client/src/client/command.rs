@@ -119,7 +119,7 @@ mod test {
     macro_rules! create_db {
         ($blob_name: ident, $db_name: ident, $accounts_expr: expr, $tree_height: expr) => {
             #[allow(unused_mut)]
-            let mut $blob_name = blob::generate($accounts_expr, 0, $tree_height);
+            let mut $blob_name = blob::generate($accounts_expr, 0, $tree_height).unwrap();
             #[allow(unused_mut)]
             let mut $db_name = Imp::<U264>::new(&mut $blob_name.proof, $tree_height + 3);
         };
@@ -146,7 +146,6 @@ mod test {
     }
 
     #[test]
-    #[ignore] // TODO: error handling in Imp(State)::value() and re-enable
     fn balance_unknown_address_ko() {
         create_db!(blob, db, 1, 256);
         assert_eq!(
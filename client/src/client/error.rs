@@ -18,4 +18,10 @@ pub enum Error {
 
     /// The address did not match a known account
     AddressUnknown(String),
+
+    /// The state database could not be read or written
+    DatabaseFailed(String),
+
+    /// The genesis accounts don't match the state the database was created with
+    GenesisMismatch(String),
 }
@@ -2,25 +2,44 @@ mod command;
 mod error;
 mod parse;
 
+use bigint::U256;
 use command::Command;
+use composer::accounts::AddressedAccount;
 use composer::blob;
+use composer::db::Database;
+use composer::smt::SparseMerkleTree;
+use composer::workload::Workload;
 use dialoguer::{theme::CustomPromptCharacterTheme, Input};
+use error::Error;
 use imp::Imp;
 use parse::parse_command;
+use sheth::account::Account;
+use sheth::state::State;
 use sheth::u264::U264;
 use std::io;
 use std::io::prelude::*;
+use std::path::Path;
 
-pub fn start(accounts: usize, tree_height: usize) {
+pub fn start(
+    accounts: Vec<AddressedAccount>,
+    tree_height: usize,
+    db_path: Option<&str>,
+    genesis: bool,
+) {
     println!("Starting sheth client");
 
     print!("Initializing database ... ");
-    io::stdout().flush().ok().expect("Could not flush stdout");
+    io::stdout().flush().expect("Could not flush stdout");
 
-    let blob = blob::generate(accounts, 0, tree_height);
-    let accounts = blob.accounts;
-    let mut proof = blob.proof;
-    let mut db = Imp::<U264>::new(&mut proof, tree_height);
+    let mut store = db_path.map(|path| {
+        open_or_create(path, accounts.clone(), tree_height, genesis).unwrap_or_else(|e| {
+            println!("{:?}", e);
+            std::process::exit(1)
+        })
+    });
+
+    let (accounts, mut proof, height) = load(store.as_ref(), accounts, tree_height);
+    let mut db = Imp::<U264>::new(&mut proof, height);
 
     println!("Ok.\n");
 
@@ -36,7 +55,10 @@ pub fn start(accounts: usize, tree_height: usize) {
 
         let result = match command {
             Ok(Command::Balance(b)) => b.execute(&db),
-            Ok(Command::Transfer(t)) => t.execute(&mut db),
+            Ok(Command::Transfer(t)) => t.execute(&mut db).and_then(|_| match store {
+                Some(ref mut store) => persist(store, &db, &[t.from, t.to]),
+                None => Ok(()),
+            }),
             Ok(Command::Exit) => std::process::exit(0),
             Ok(Command::Accounts(a)) => a.execute(&accounts),
             Err(e) => Err(e),
@@ -47,3 +69,133 @@ pub fn start(accounts: usize, tree_height: usize) {
         }
     }
 }
+
+/// Returns the accounts and their proof, either from the database or generated from `accounts`,
+/// along with the height of the proof for `Imp`. The proof also covers the three levels of each
+/// account's fields below the state tree, as in `sheth::process_data_blob`.
+fn load(
+    store: Option<&Database>,
+    accounts: Vec<AddressedAccount>,
+    tree_height: usize,
+) -> (Vec<AddressedAccount>, Vec<u8>, usize) {
+    match store {
+        Some(store) => {
+            let accounts = store.tree().accounts();
+            let addresses: Vec<U256> = accounts.iter().map(|a| a.0).collect();
+            let proof = store.proof(&addresses).expect("Accounts to be in the tree");
+
+            (accounts, proof.to_bytes(), store.tree().height() + 3)
+        }
+        None => {
+            let blob = blob::generate_from_accounts(accounts, 0, tree_height, &Workload::default());
+            (blob.accounts, blob.proof, tree_height + 3)
+        }
+    }
+}
+
+/// Opens the database at `path`, or creates it with `accounts` if it doesn't exist. If the
+/// accounts were loaded from a `genesis` file, they must match the database's genesis state.
+fn open_or_create(
+    path: &str,
+    accounts: Vec<AddressedAccount>,
+    tree_height: usize,
+    genesis: bool,
+) -> Result<Database, Error> {
+    let failed = |e| Error::DatabaseFailed(format!("{:?}", e));
+
+    if !Path::new(path).exists() {
+        return Database::create(path, accounts, tree_height).map_err(failed);
+    }
+
+    let store = Database::open(path).map_err(failed)?;
+
+    if genesis {
+        let root = SparseMerkleTree::from_accounts(accounts, store.tree().height())
+            .map_err(failed)?
+            .root();
+
+        if store.root_at(0) != Some(root) {
+            return Err(Error::GenesisMismatch(path.to_string()));
+        }
+    }
+
+    Ok(store)
+}
+
+/// Copies the accounts at `addresses` from the client's proof into the database as a new block.
+fn persist(store: &mut Database, db: &Imp<U264>, addresses: &[U256]) -> Result<(), Error> {
+    let mut updates = vec![];
+
+    for address in addresses {
+        let mut account = store
+            .tree()
+            .get(address)
+            .cloned()
+            .unwrap_or_else(Account::zero);
+
+        let unknown = |_| Error::AddressUnknown(format!("{:x}", address));
+        account.nonce = db.nonce((*address).into()).map_err(unknown)?;
+        account.value = db.value((*address).into()).map_err(unknown)?;
+
+        updates.push(AddressedAccount(*address, account));
+    }
+
+    store
+        .apply_updates(updates)
+        .map_err(|e| Error::DatabaseFailed(format!("{:?}", e)))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use composer::accounts::{random_accounts, random_accounts_with_seed};
+
+    #[test]
+    fn load_proof_of_accounts() {
+        let accounts = random_accounts(3, 16).unwrap();
+        let root = SparseMerkleTree::from_accounts(accounts.clone(), 16)
+            .unwrap()
+            .root();
+
+        let mut path = std::env::temp_dir();
+        path.push(format!("client-load-{}", std::process::id()));
+        let store = Database::create(&path, accounts.clone(), 16).unwrap();
+
+        for store in [None, Some(&store)].iter().cloned() {
+            let (loaded, mut proof, height) = load(store, accounts.clone(), 16);
+            let mut db = Imp::<U264>::new(&mut proof, height);
+
+            assert_eq!(loaded.len(), 3);
+            assert_eq!(State::root(&mut db), Ok(root));
+
+            for AddressedAccount(address, account) in &accounts {
+                assert_eq!(db.nonce((*address).into()), Ok(account.nonce));
+                assert_eq!(db.value((*address).into()), Ok(account.value));
+            }
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn genesis_must_match_database() {
+        let accounts = random_accounts(3, 16).unwrap();
+        let other = random_accounts_with_seed(3, 16, 1).unwrap();
+
+        let mut path = std::env::temp_dir();
+        path.push(format!("client-genesis-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        assert!(open_or_create(path, accounts.clone(), 16, true).is_ok());
+        assert!(open_or_create(path, accounts, 16, true).is_ok());
+        assert!(open_or_create(path, other.clone(), 16, false).is_ok());
+        assert_eq!(
+            open_or_create(path, other, 16, true).err(),
+            Some(Error::GenesisMismatch(path.to_string()))
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
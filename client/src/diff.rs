@@ -0,0 +1,92 @@
+use crate::inspect::read_blob;
+use composer::blob::Blob;
+use composer::db::Database;
+use composer::diff::{compare_proofs, compare_state, Difference};
+
+/// Compares the proofs of two blobs, each given as hex or as a file containing one.
+pub fn build(left: &str, right: &str, height: usize) -> Result<String, String> {
+    let left = proof(left, height)?;
+    let right = proof(right, height)?;
+
+    let differences =
+        compare_proofs(&left, &right, height).map_err(|e| format!("Invalid proof: {:?}", e))?;
+
+    Ok(report(&differences))
+}
+
+/// Compares the proof of a blob with the latest state of the database at `path`.
+pub fn with_database(left: &str, path: &str) -> Result<String, String> {
+    let db = Database::open(path).map_err(|e| format!("Unable to open database: {:?}", e))?;
+    let left = proof(left, db.tree().height())?;
+
+    let differences =
+        compare_state(&left, db.tree()).map_err(|e| format!("Invalid proof: {:?}", e))?;
+
+    Ok(report(&differences))
+}
+
+fn proof(input: &str, height: usize) -> Result<Vec<u8>, String> {
+    let blob = read_blob(input)?;
+    let blob = Blob::from_bytes(&blob, height).map_err(|e| format!("Invalid blob: {:?}", e))?;
+
+    Ok(blob.proof)
+}
+
+fn report(differences: &[Difference]) -> String {
+    if differences.is_empty() {
+        return "No differences\n".to_string();
+    }
+
+    differences.iter().fold(
+        format!("Differences ({}):\n", differences.len()),
+        |acc, d| acc + &d.to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use composer::accounts::random_accounts;
+    use composer::blob::BlobBuilder;
+    use sheth::transaction::{Transaction, Transfer};
+
+    #[test]
+    fn diff_packages() {
+        let accounts = random_accounts(2, 8).unwrap();
+        let (before, _, _) = BlobBuilder::new(8)
+            .accounts(accounts.clone())
+            .build()
+            .unwrap();
+
+        let mut updated = accounts.clone();
+        updated[1].1.value += 5;
+        let (after, _, _) = BlobBuilder::new(8).accounts(updated).build().unwrap();
+
+        let before = hex::encode(before.to_bytes());
+        let after = hex::encode(after.to_bytes());
+
+        assert_eq!(build(&before, &before, 8).unwrap(), "No differences\n");
+
+        let output = build(&before, &after, 8).unwrap();
+        assert!(output.starts_with("Differences (1):"));
+        assert!(output.contains("Value"));
+        assert!(output.contains(&format!("({})", accounts[1].1.value + 5)));
+
+        let tx = Transaction::Transfer(Transfer {
+            to: accounts[0].0.into(),
+            from: accounts[1].0.into(),
+            nonce: accounts[1].1.nonce,
+            amount: 0,
+            signature: [0u8; 96],
+        });
+        let (with_tx, _, _) = BlobBuilder::new(8)
+            .accounts(accounts)
+            .transaction(tx)
+            .build()
+            .unwrap();
+        assert_eq!(
+            build(&before, &hex::encode(with_tx.to_bytes()), 8).unwrap(),
+            "No differences\n"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use composer::inspect::inspect;
+use std::fs;
+use std::path::Path;
+
+/// Decodes a blob and describes its transactions and proof. The `input` is either a hex encoded
+/// blob or a file containing one. When the file holds the output of `package`, the blob is the
+/// last of its values.
+pub fn build(input: &str, height: usize) -> Result<String, String> {
+    let blob = read_blob(input)?;
+    let inspection = inspect(&blob, height).map_err(|e| format!("Invalid blob: {:?}", e))?;
+
+    Ok(inspection.to_string())
+}
+
+/// Returns the blob which is either hex encoded in `input`, or stored in the file at `input`.
+pub fn read_blob(input: &str) -> Result<Vec<u8>, String> {
+    let text = if Path::new(input).is_file() {
+        fs::read_to_string(input).map_err(|e| format!("Unable to read {}: {}", input, e))?
+    } else {
+        input.to_string()
+    };
+
+    let encoded = text.split_whitespace().last().unwrap_or("");
+    let encoded = encoded.strip_prefix("0x").unwrap_or(encoded);
+
+    hex::decode(encoded).map_err(|e| format!("Invalid hex: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use composer::blob;
+
+    #[test]
+    fn inspect_package_output() {
+        let blob = hex::encode(blob::generate(2, 1, 8).unwrap().to_bytes());
+
+        let output = build(&format!("00 11 0x{}", blob), 8).unwrap();
+        assert!(output.starts_with("Transactions (1):"));
+
+        assert!(build("0xzz", 8).is_err());
+        assert!(build(&blob[..10], 8).is_err());
+    }
+}
@@ -1,10 +1,18 @@
 #[macro_use]
 extern crate clap;
 
+mod analyze;
 mod client;
+mod diff;
+mod inspect;
 mod package;
+mod render;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use composer::accounts::{generate_accounts, AddressedAccount, Addresses};
+use composer::genesis;
+use composer::render::Format;
+use composer::workload::{parse_invalid, parse_rate, Amount, Distribution, Workload};
 
 fn main() {
     let matches = App::new("sheth-client")
@@ -25,6 +33,57 @@ fn main() {
                         .required(true)
                         .help("number of transactions to be generated"),
                 )
+                .arg(
+                    Arg::with_name("addresses")
+                        .long("addresses")
+                        .takes_value(true)
+                        .default_value("random")
+                        .validator(|s| s.parse::<Addresses>().map(|_| ()))
+                        .help("assigns the addresses of generated accounts: random, sequential, or a comma separated list"),
+                )
+                .args(&package_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("package-genesis")
+                .about("Builds a random transaction package for the accounts of a genesis file")
+                .version("0.0.1")
+                .arg(
+                    Arg::with_name("genesis")
+                        .required(true)
+                        .help("genesis file that the accounts are loaded from"),
+                )
+                .arg(
+                    Arg::with_name("transactions")
+                        .required(true)
+                        .help("number of transactions to be generated"),
+                )
+                .args(&package_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Decodes a transaction package")
+                .arg(
+                    Arg::with_name("blob")
+                        .required(true)
+                        .help("hex encoded blob, or a file containing one"),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .short("d")
+                        .takes_value(true)
+                        .default_value("256")
+                        .help("defines the height of sparse state structure"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Measures the size of a transaction package and the work to execute it")
+                .arg(
+                    Arg::with_name("blob")
+                        .required(true)
+                        .help("hex encoded blob, or a file containing one"),
+                )
                 .arg(
                     Arg::with_name("height")
                         .long("height")
@@ -32,11 +91,61 @@ fn main() {
                         .takes_value(true)
                         .default_value("256")
                         .help("defines the height of sparse state structure"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("Draws the proof of a transaction package")
+                .arg(
+                    Arg::with_name("blob")
+                        .required(true)
+                        .help("hex encoded blob, or a file containing one"),
                 )
                 .arg(
-                    Arg::with_name("scout")
-                        .long("scout")
-                        .help("When set, the output will be in the format of a Scout YAML file"),
+                    Arg::with_name("format")
+                        .long("format")
+                        .short("f")
+                        .takes_value(true)
+                        .default_value("ascii")
+                        .validator(|s| s.parse::<Format>().map(|_| ()))
+                        .help("outputs an ascii tree, or a Graphviz digraph with dot"),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .short("d")
+                        .takes_value(true)
+                        .default_value("256")
+                        .help("defines the height of sparse state structure"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compares the proofs of two transaction packages, or a package and a database")
+                .arg(
+                    Arg::with_name("left")
+                        .required(true)
+                        .help("hex encoded blob, or a file containing one"),
+                )
+                .arg(
+                    Arg::with_name("right")
+                        .required_unless("db")
+                        .help("hex encoded blob, or a file containing one"),
+                )
+                .arg(
+                    Arg::with_name("db")
+                        .long("db")
+                        .takes_value(true)
+                        .conflicts_with("right")
+                        .help("compares the left package with the state of a database"),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .short("d")
+                        .takes_value(true)
+                        .default_value("256")
+                        .help("defines the height of sparse state structure"),
                 ),
         )
         .subcommand(
@@ -44,7 +153,7 @@ fn main() {
                 .about("Starts a Sheth client")
                 .arg(
                     Arg::with_name("accounts")
-                        .required(true)
+                        .required_unless("genesis")
                         .help("number of accounts that will be represented in the proof"),
                 )
                 .arg(
@@ -54,26 +163,219 @@ fn main() {
                         .takes_value(true)
                         .default_value("256")
                         .help("defines the height of sparse state structure"),
+                )
+                .arg(
+                    Arg::with_name("genesis")
+                        .long("genesis")
+                        .takes_value(true)
+                        .conflicts_with("accounts")
+                        .help("loads the accounts from a genesis file instead of generating them"),
+                )
+                .arg(
+                    Arg::with_name("addresses")
+                        .long("addresses")
+                        .takes_value(true)
+                        .default_value("random")
+                        .validator(|s| s.parse::<Addresses>().map(|_| ()))
+                        .help("assigns the addresses of generated accounts: random, sequential, or a comma separated list"),
+                )
+                .arg(
+                    Arg::with_name("db")
+                        .long("db")
+                        .takes_value(true)
+                        .help("persists the state to a database file, creating it if necessary"),
                 ),
         )
         .get_matches();
 
     // Run packager
-    if let Some(matches) = matches.subcommand_matches("package") {
-        let accounts = value_t!(matches.value_of("accounts"), usize).unwrap_or_else(|e| e.exit());
-        let txs = value_t!(matches.value_of("transactions"), usize).unwrap_or_else(|e| e.exit());
+    for name in &["package", "package-genesis"] {
+        if let Some(matches) = matches.subcommand_matches(name) {
+            let txs =
+                value_t!(matches.value_of("transactions"), usize).unwrap_or_else(|e| e.exit());
+            let height = value_t!(matches.value_of("height"), usize).unwrap_or_else(|e| e.exit());
+            let blocks = value_t!(matches.value_of("blocks"), usize).unwrap_or_else(|e| e.exit());
+            let scout = matches.is_present("scout");
+            let workload = workload(matches);
+            let accounts = accounts(matches, height, workload.seed);
+
+            match package::build(accounts, blocks, txs, height, scout, &workload) {
+                Ok(output) => println!("{}", output),
+                Err(e) => {
+                    println!("{:?}", e);
+                    std::process::exit(1)
+                }
+            }
+        }
+    }
+
+    // Run inspector
+    if let Some(matches) = matches.subcommand_matches("inspect") {
+        let height = value_t!(matches.value_of("height"), usize).unwrap_or_else(|e| e.exit());
+
+        match inspect::build(matches.value_of("blob").unwrap(), height) {
+            Ok(output) => print!("{}", output),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1)
+            }
+        }
+    }
+
+    // Run analyzer
+    if let Some(matches) = matches.subcommand_matches("analyze") {
+        let height = value_t!(matches.value_of("height"), usize).unwrap_or_else(|e| e.exit());
+
+        match analyze::build(matches.value_of("blob").unwrap(), height) {
+            Ok(output) => println!("{}", output),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1)
+            }
+        }
+    }
+
+    // Run renderer
+    if let Some(matches) = matches.subcommand_matches("render") {
+        let height = value_t!(matches.value_of("height"), usize).unwrap_or_else(|e| e.exit());
+        let format = value_t!(matches.value_of("format"), Format).unwrap_or_else(|e| e.exit());
+
+        match render::build(matches.value_of("blob").unwrap(), height, format) {
+            Ok(output) => print!("{}", output),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1)
+            }
+        }
+    }
+
+    // Run diff
+    if let Some(matches) = matches.subcommand_matches("diff") {
         let height = value_t!(matches.value_of("height"), usize).unwrap_or_else(|e| e.exit());
-        let scout = matches.is_present("scout");
+        let left = matches.value_of("left").unwrap();
+
+        let result = match matches.value_of("db") {
+            Some(db) => diff::with_database(left, db),
+            None => diff::build(left, matches.value_of("right").unwrap(), height),
+        };
 
-        let output = package::build(accounts, txs, height, scout);
-        println!("{}", output);
+        match result {
+            Ok(output) => print!("{}", output),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1)
+            }
+        }
     }
 
     // Start client
     if let Some(matches) = matches.subcommand_matches("start") {
-        let accounts = value_t!(matches.value_of("accounts"), usize).unwrap_or_else(|e| e.exit());
         let height = value_t!(matches.value_of("height"), usize).unwrap_or_else(|e| e.exit());
+        let db = matches.value_of("db");
+        let accounts = accounts(matches, height, Workload::default().seed);
+
+        client::start(accounts, height, db, matches.is_present("genesis"));
+    }
+}
+
+/// Returns the options shared by the packagers, which describe the blocks and their workload.
+fn package_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("height")
+            .long("height")
+            .short("d")
+            .takes_value(true)
+            .default_value("256")
+            .help("defines the height of sparse state structure"),
+        Arg::with_name("blocks")
+            .long("blocks")
+            .takes_value(true)
+            .default_value("1")
+            .validator(|s| match s.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(()),
+                _ => Err("must be a positive number".to_string()),
+            })
+            .help("number of consecutive blocks, each spending the state left by the previous one"),
+        Arg::with_name("scout")
+            .long("scout")
+            .help("When set, the output will be in the format of a Scout YAML file"),
+        Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .default_value("42")
+            .help("seeds the generation of accounts and transactions"),
+        Arg::with_name("distribution")
+            .long("distribution")
+            .takes_value(true)
+            .default_value("uniform")
+            .validator(|s| s.parse::<Distribution>().map(|_| ()))
+            .help(
+                "picks senders and recipients: uniform, zipf:<exponent>, or hot:<accounts>:<rate>",
+            ),
+        Arg::with_name("self-transfer-rate")
+            .long("self-transfer-rate")
+            .takes_value(true)
+            .validator(|s| parse_rate(&s).map(|_| ()))
+            .help("probability that a transaction is sent to its own sender"),
+        Arg::with_name("amount")
+            .long("amount")
+            .takes_value(true)
+            .default_value("uniform")
+            .validator(|s| s.parse::<Amount>().map(|_| ()))
+            .help("picks transfer amounts: uniform, fixed:<amount>, or range:<min>:<max>"),
+        Arg::with_name("invalid-rate")
+            .long("invalid-rate")
+            .takes_value(true)
+            .default_value("0")
+            .validator(|s| parse_rate(&s).map(|_| ()))
+            .help("probability that a transaction is replaced by an invalid one"),
+        Arg::with_name("invalid")
+            .long("invalid")
+            .takes_value(true)
+            .default_value("nonce,overdraft,overflow,unknown-address,signature")
+            .validator(|s| parse_invalid(&s).map(|_| ()))
+            .help("comma separated kinds of invalid transactions to inject"),
+    ]
+}
+
+/// Returns the accounts from the genesis file if one was given, otherwise the requested number of
+/// random accounts.
+fn accounts(matches: &ArgMatches, height: usize, seed: u64) -> Vec<AddressedAccount> {
+    match matches.value_of("genesis") {
+        Some(path) => genesis::load(path, height).unwrap_or_else(|e| {
+            println!("Unable to load genesis: {:?}", e);
+            std::process::exit(1)
+        }),
+        None => {
+            let n = value_t!(matches.value_of("accounts"), usize).unwrap_or_else(|e| e.exit());
+            let addresses =
+                value_t!(matches.value_of("addresses"), Addresses).unwrap_or_else(|e| e.exit());
+
+            generate_accounts(n, height, seed, &addresses).unwrap_or_else(|e| {
+                println!("Unable to generate accounts: {:?}", e);
+                std::process::exit(1)
+            })
+        }
+    }
+}
 
-        client::start(accounts, height);
+/// Returns the workload described by the packager's arguments.
+fn workload(matches: &ArgMatches) -> Workload {
+    Workload {
+        seed: value_t!(matches.value_of("seed"), u64).unwrap_or_else(|e| e.exit()),
+        addresses: match matches.value_of("addresses") {
+            Some(_) => {
+                value_t!(matches.value_of("addresses"), Addresses).unwrap_or_else(|e| e.exit())
+            }
+            None => Addresses::default(),
+        },
+        distribution: value_t!(matches.value_of("distribution"), Distribution)
+            .unwrap_or_else(|e| e.exit()),
+        self_transfer_rate: matches
+            .value_of("self-transfer-rate")
+            .map(|s| parse_rate(s).unwrap()),
+        amount: value_t!(matches.value_of("amount"), Amount).unwrap_or_else(|e| e.exit()),
+        invalid_rate: parse_rate(matches.value_of("invalid-rate").unwrap()).unwrap(),
+        invalid: parse_invalid(matches.value_of("invalid").unwrap()).unwrap(),
     }
 }
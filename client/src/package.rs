@@ -1,9 +1,33 @@
+use composer::accounts::AddressedAccount;
 use composer::blob;
+use composer::error::Error;
+use composer::workload::Workload;
 
-pub fn build(accounts: usize, transactions: usize, height: usize, scout: bool) -> String {
-    let (blob, pre_state, post_state) = blob::generate_with_roots(accounts, transactions, height);
+pub fn build(
+    accounts: Vec<AddressedAccount>,
+    blocks: usize,
+    transactions: usize,
+    height: usize,
+    scout: bool,
+    workload: &Workload,
+) -> Result<String, Error> {
+    let chain = blob::generate_chain(accounts, blocks, transactions, height, workload)?;
+
+    // A rejected block leaves the state untouched.
+    let chain: Vec<([u8; 32], [u8; 32], String)> = chain
+        .into_iter()
+        .map(|(blob, pre_state, post_state)| {
+            let post_state = post_state.unwrap_or(pre_state);
+            (pre_state, post_state, hex::encode(blob.to_bytes()))
+        })
+        .collect();
+
+    let output = if scout {
+        let blocks: Vec<String> = chain
+            .iter()
+            .map(|(_, _, data)| format!("    - env: 0\n      data: \"{}\"", data))
+            .collect();
 
-    if scout {
         format!(
             "\
 beacon_state:
@@ -13,21 +37,28 @@ shard_pre_state:
     exec_env_states:
         - \"{}\"
 shard_blocks:
-    - env: 0
-      data: \"{}\"
+{}
 shard_post_state:
     exec_env_states:
         - \"{}\"",
-            hex::encode(pre_state),
-            hex::encode(blob.to_bytes()),
-            hex::encode(post_state)
+            hex::encode(chain[0].0),
+            blocks.join("\n"),
+            hex::encode(chain[chain.len() - 1].1)
         )
     } else {
-        format!(
-            "{} {} {}",
-            hex::encode(pre_state),
-            hex::encode(post_state),
-            hex::encode(blob.to_bytes()),
-        )
-    }
+        chain
+            .iter()
+            .map(|(pre_state, post_state, data)| {
+                format!(
+                    "{} {} {}",
+                    hex::encode(pre_state),
+                    hex::encode(post_state),
+                    data
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    Ok(output)
 }
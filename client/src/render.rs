@@ -0,0 +1,31 @@
+use crate::inspect::read_blob;
+use composer::blob::Blob;
+use composer::render::{render, Format};
+
+/// Renders the proof of a blob, which is either hex encoded in `input` or stored in the file at
+/// `input`.
+pub fn build(input: &str, height: usize, format: Format) -> Result<String, String> {
+    let blob = read_blob(input)?;
+    let blob = Blob::from_bytes(&blob, height).map_err(|e| format!("Invalid blob: {:?}", e))?;
+
+    render(&blob.proof, height, format).map_err(|e| format!("Invalid proof: {:?}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use composer::blob;
+
+    #[test]
+    fn render_package_output() {
+        let blob = hex::encode(blob::generate(2, 1, 8).unwrap().to_bytes());
+
+        let output = build(&blob, 8, Format::Ascii).unwrap();
+        assert!(output.starts_with("1 branch at depth 0 [computed]"));
+
+        let output = build(&blob, 8, Format::Dot).unwrap();
+        assert!(output.starts_with("digraph proof {"));
+
+        assert!(build(&blob[..10], 8, Format::Dot).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use composer::accounts::random_accounts;
+use composer::proof::uncompressed::generate;
+use composer::smt::SparseMerkleTree;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+// Proof generation should scale close to linearly in the number of accounts, so the throughput
+// (accounts per second) reported for each size should stay roughly the same.
+fn proof_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("proof_generation");
+    group.sample_size(10);
+
+    for n in [500, 1000, 2000, 4000, 10000, 20000, 40000].iter() {
+        let accounts = random_accounts(*n, 256).unwrap();
+
+        group.throughput(Throughput::Elements(*n as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &accounts, |b, accounts| {
+            b.iter(|| generate(accounts.clone(), 256).to_bytes())
+        });
+    }
+
+    group.finish();
+}
+
+// Calculating the root of the full state, which hashes every node above the accounts.
+fn state_root(c: &mut Criterion) {
+    let mut group = c.benchmark_group("state_root");
+    group.sample_size(10);
+
+    for n in [1000, 4000].iter() {
+        let accounts = random_accounts(*n, 256).unwrap();
+
+        group.throughput(Throughput::Elements(*n as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &accounts, |b, accounts| {
+            b.iter(|| {
+                SparseMerkleTree::from_accounts(accounts.clone(), 256)
+                    .unwrap()
+                    .root()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, proof_generation, state_root);
+criterion_main!(benches);
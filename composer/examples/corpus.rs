@@ -0,0 +1,75 @@
+//! Writes the seed corpus of the fuzz targets in `fuzz/` to the directory given as the first
+//! argument (`fuzz/corpus` by default). Each seed is a blob of a tree of height 256, like the
+//! blobs `sheth` executes, and every target gets the part of the blob it decodes.
+
+use composer::accounts::Addresses;
+use composer::blob::{self, Blob};
+use composer::workload::Workload;
+use std::convert::TryInto;
+use std::path::Path;
+use std::{env, fs, io};
+
+const HEIGHT: usize = 256;
+
+/// Returns the named seed blobs and their pre-state roots.
+fn seeds() -> Vec<(&'static str, Blob, [u8; 32])> {
+    let sequential = |invalid_rate| Workload {
+        addresses: Addresses::Sequential,
+        invalid_rate,
+        ..Workload::default()
+    };
+
+    let blobs = vec![
+        ("empty", 1, 0, sequential(0.0)),
+        ("transfer", 2, 1, sequential(0.0)),
+        ("transfers", 4, 8, sequential(0.0)),
+        ("invalid", 3, 4, sequential(1.0)),
+        ("random-addresses", 2, 2, Workload::default()),
+    ];
+
+    blobs
+        .into_iter()
+        .map(|(name, accounts, transactions, workload)| {
+            let blob =
+                blob::generate_with_workload(accounts, transactions, HEIGHT, &workload).unwrap();
+            let (pre_state, _) = blob::process(&blob, HEIGHT);
+
+            (name, blob, pre_state)
+        })
+        .collect()
+}
+
+fn write(dir: &Path, target: &str, name: &str, data: &[u8]) -> io::Result<()> {
+    let dir = dir.join(target);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(name), data)
+}
+
+fn main() -> io::Result<()> {
+    let dir = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "fuzz/corpus".to_string());
+    let dir = Path::new(&dir);
+
+    for (name, blob, pre_state) in seeds() {
+        let bytes = blob.to_bytes();
+
+        let transactions = 4 + blob.transactions.len() * 176;
+        let offsets = u64::from_le_bytes(blob.proof[0..8].try_into().unwrap()) as usize * 8;
+
+        let mut rooted = pre_state.to_vec();
+        rooted.extend(&bytes);
+
+        write(
+            dir,
+            "deserialize_transactions",
+            name,
+            &bytes[..transactions],
+        )?;
+        write(dir, "process_data_blob", name, &rooted)?;
+        write(dir, "blob", name, &bytes)?;
+        write(dir, "offsets", name, &blob.proof[..offsets])?;
+    }
+
+    Ok(())
+}
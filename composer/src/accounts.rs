@@ -1,9 +1,12 @@
+use crate::error::Error;
+use crate::genesis::MAX_HEIGHT;
 use bigint::U256;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use sha2::{Digest, Sha256};
 use sheth::account::Account;
 use sheth::bls::PublicKey;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
 /// A tuple consisting of an `Account` and its address.
 ///
@@ -12,27 +15,128 @@ use std::collections::HashMap;
 #[derive(Clone)]
 pub struct AddressedAccount(pub U256, pub Account);
 
-pub fn random_accounts(n: usize, height: usize) -> Vec<AddressedAccount> {
-    // TODO: check that the number of accounts can be generated from the tree
+/// How the addresses of generated accounts are assigned.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Addresses {
+    /// Each address is derived from the account's random public key
+    #[default]
+    Random,
 
-    let mut rng = StdRng::seed_from_u64(42);
+    /// The accounts are at addresses `0, 1, 2, ...`
+    Sequential,
+
+    /// The accounts are at the given addresses, in order
+    Chosen(Vec<U256>),
+}
+
+/// Parses `random`, `sequential`, or a comma separated list of addresses, which are either
+/// decimal or `0x` prefixed hex.
+impl FromStr for Addresses {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Addresses::Random),
+            "sequential" => Ok(Addresses::Sequential),
+            _ => s
+                .split(',')
+                .map(|address| {
+                    let address = address.trim();
+                    match address.strip_prefix("0x") {
+                        Some(hex) => parse_hex(hex),
+                        None => U256::from_dec_str(address).ok(),
+                    }
+                    .ok_or_else(|| format!("invalid address: {}", address))
+                })
+                .collect::<Result<Vec<U256>, String>>()
+                .map(Addresses::Chosen),
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Option<U256> {
+    if s.is_empty() || s.len() > 64 {
+        return None;
+    }
+
+    hex::decode(format!("{:0>64}", s))
+        .ok()
+        .map(|bytes| U256::from(&bytes[..]))
+}
+
+pub fn random_accounts(n: usize, height: usize) -> Result<Vec<AddressedAccount>, Error> {
+    random_accounts_with_seed(n, height, 42)
+}
+
+/// Generate `n` random accounts in a tree of the specified height, using `seed` to seed the
+/// random number generator.
+pub fn random_accounts_with_seed(
+    n: usize,
+    height: usize,
+    seed: u64,
+) -> Result<Vec<AddressedAccount>, Error> {
+    generate_accounts(n, height, seed, &Addresses::Random)
+}
+
+/// Generate `n` accounts with random keys and balances in a tree of the specified height, where
+/// `addresses` decides where each account is placed. Fails if the tree (or the list of chosen
+/// addresses) doesn't have room for `n` accounts.
+pub fn generate_accounts(
+    n: usize,
+    height: usize,
+    seed: u64,
+    addresses: &Addresses,
+) -> Result<Vec<AddressedAccount>, Error> {
+    if height == 0 || height > MAX_HEIGHT {
+        return Err(Error::HeightOutOfRange(height));
+    }
+
+    let capacity_exceeded = match addresses {
+        Addresses::Chosen(chosen) => n > chosen.len(),
+        _ => height < 64 && n as u64 > 1u64 << height,
+    };
+
+    if capacity_exceeded {
+        return Err(Error::CapacityExceeded(n));
+    }
+
+    if let Addresses::Chosen(chosen) = addresses {
+        let mut seen = HashSet::new();
+
+        for address in chosen.iter().take(n) {
+            if height < 256 && *address >= U256::one() << height {
+                return Err(Error::AddressOutOfRange(*address));
+            }
+
+            if !seen.insert(*address) {
+                return Err(Error::AddressCollision(*address));
+            }
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
     let mut map: HashMap<U256, bool> = HashMap::new();
 
-    (0..n).fold(vec![], |mut acc, _| {
+    Ok((0..n).fold(vec![], |mut acc, i| {
         let mut pubkey = [0u8; 48];
-        let address = loop {
-            rng.fill(&mut pubkey[..]);
 
-            // Hash public key to get address
-            let mut address = U256::from(Sha256::digest(&pubkey).as_ref());
+        let address = match addresses {
+            Addresses::Random => loop {
+                rng.fill(&mut pubkey[..]);
 
-            if height < 256 {
-                address = address % (U256::one() << height);
-            }
+                let address = address_from_pubkey(&pubkey, height);
 
-            if !map.contains_key(&address) {
-                map.insert(address, true);
-                break address;
+                if map.insert(address, true).is_none() {
+                    break address;
+                }
+            },
+            Addresses::Sequential => {
+                rng.fill(&mut pubkey[..]);
+                U256::from(i)
+            }
+            Addresses::Chosen(chosen) => {
+                rng.fill(&mut pubkey[..]);
+                chosen[i]
             }
         };
 
@@ -45,11 +149,19 @@ pub fn random_accounts(n: usize, height: usize) -> Vec<AddressedAccount> {
             },
         ));
 
-        let mut buf = [0u8; 32];
-        address.to_big_endian(&mut buf);
-
         acc
-    })
+    }))
+}
+
+/// Hash the public key to get the account's address in a tree of the specified height.
+pub fn address_from_pubkey(pubkey: &[u8; 48], height: usize) -> U256 {
+    let address = U256::from(Sha256::digest(pubkey).as_ref());
+
+    if height < 256 {
+        address % (U256::one() << height)
+    } else {
+        address
+    }
 }
 
 #[cfg(test)]
@@ -58,7 +170,7 @@ mod test {
 
     #[test]
     fn generates_random_accounts() {
-        let accounts = random_accounts(2, 256);
+        let accounts = random_accounts(2, 256).unwrap();
 
         for AddressedAccount(address, account) in accounts {
             assert_eq!(
@@ -71,4 +183,51 @@ mod test {
             assert_ne!(account.value, 0);
         }
     }
+
+    #[test]
+    fn capacity_is_checked() {
+        assert_eq!(random_accounts(4, 2).unwrap().len(), 4);
+        assert_eq!(
+            random_accounts(5, 2).err(),
+            Some(Error::CapacityExceeded(5))
+        );
+        assert_eq!(
+            random_accounts(1, 0).err(),
+            Some(Error::HeightOutOfRange(0))
+        );
+
+        let chosen = Addresses::Chosen(vec![3.into(), 1.into()]);
+        assert_eq!(
+            generate_accounts(3, 2, 42, &chosen).err(),
+            Some(Error::CapacityExceeded(3))
+        );
+    }
+
+    #[test]
+    fn deterministic_addresses() {
+        let addresses = |accounts: Vec<AddressedAccount>| -> Vec<U256> {
+            accounts.iter().map(|a| a.0).collect()
+        };
+
+        let sequential = generate_accounts(3, 4, 42, &Addresses::Sequential).unwrap();
+        assert_eq!(addresses(sequential), vec![0.into(), 1.into(), 2.into()]);
+
+        let chosen = "0x0f,7".parse().unwrap();
+        let accounts = generate_accounts(2, 4, 42, &chosen).unwrap();
+        assert_eq!(addresses(accounts), vec![15.into(), 7.into()]);
+
+        let chosen = Addresses::Chosen(vec![16.into()]);
+        assert_eq!(
+            generate_accounts(1, 4, 42, &chosen).err(),
+            Some(Error::AddressOutOfRange(16.into()))
+        );
+
+        let chosen = Addresses::Chosen(vec![1.into(), 1.into()]);
+        assert_eq!(
+            generate_accounts(2, 4, 42, &chosen).err(),
+            Some(Error::AddressCollision(1.into()))
+        );
+
+        assert!("0x,1".parse::<Addresses>().is_err());
+    }
 }
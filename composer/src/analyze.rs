@@ -0,0 +1,264 @@
+use crate::blob::{process, Blob};
+use crate::error::Error;
+use crate::inspect::{inspect, Node};
+use crate::smt::SparseMerkleTree;
+use bigint::{U256, U512};
+use serde::Serialize;
+use sheth::process::process_transactions;
+use sheth::transaction::Transaction;
+use std::collections::HashSet;
+
+/// Measures the size of a blob and the work needed to execute it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub transactions: usize,
+
+    /// Number of complete accounts in the proof
+    pub accounts: usize,
+
+    /// Number of distinct addresses referenced by the transactions
+    pub touched: usize,
+
+    pub bytes: Bytes,
+    pub siblings: Siblings,
+    pub hashes: Hashes,
+
+    pub pre_state: String,
+
+    /// The post-state root, unless the block is rejected
+    pub post_state: Option<String>,
+
+    /// The reason the block is rejected
+    pub rejected: Option<String>,
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Report to be serializable")
+    }
+}
+
+/// Number of bytes spent on each part of a blob.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Bytes {
+    pub total: usize,
+
+    /// The transaction count and the transactions
+    pub transactions: usize,
+
+    /// The offsets table of the proof, including its length
+    pub offsets: usize,
+
+    /// The fields of the accounts (e.g. the nonces and values)
+    pub leaves: usize,
+
+    /// Every other chunk of the proof
+    pub siblings: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Siblings {
+    pub total: usize,
+
+    /// Siblings which are the root of an empty subtree
+    pub zero: usize,
+}
+
+/// Number of hashes needed to execute the blob.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Hashes {
+    /// Hashes needed to calculate the pre-state root from the proof
+    pub pre_state: usize,
+
+    /// Hashes needed to calculate the post-state root. The whole proof is rehashed, so this is
+    /// the same as `pre_state`. This is `None` if the block is rejected.
+    pub post_state: Option<usize>,
+
+    /// Nodes above the leaves which are changed by the transactions, i.e. the hashes needed if
+    /// only the updated branches were rehashed. This is `None` if the block is rejected.
+    pub updated: Option<usize>,
+}
+
+/// Analyzes a blob (e.g. the output of `Blob::to_bytes`) from a tree of height `height`.
+pub fn analyze(bytes: &[u8], height: usize) -> Result<Report, Error> {
+    let inspection = inspect(bytes, height)?;
+    let blob = Blob::from_inspection(bytes, &inspection);
+
+    let leaves = inspection
+        .chunks
+        .iter()
+        .filter(|chunk| matches!(chunk.node, Node::Account(_, field) if field.is_leaf()))
+        .count();
+    let siblings = inspection.chunks.len() - leaves;
+    let zero = inspection.chunks.iter().filter(|chunk| chunk.zero).count();
+
+    let transactions = 4 + blob.transactions.len() * 176;
+    let offsets = inspection.offsets.len() * 8;
+
+    let touched: HashSet<U256> = blob
+        .transactions
+        .iter()
+        .flat_map(|tx| match tx {
+            Transaction::Transfer(t) => vec![t.to, t.from],
+            _ => vec![],
+        })
+        .map(|address| U256::from(<[u8; 32]>::from(address)))
+        .collect();
+
+    let (pre_state, post_state) = process(&blob, height);
+
+    // Each chunk is a leaf of a full binary tree, so there is one less node above them
+    let root_hashes = inspection.chunks.len() - 1;
+
+    let (post_state, post_hashes, updated, rejected) = match post_state {
+        Ok(root) => (
+            Some(format!("0x{}", hex::encode(root))),
+            Some(root_hashes),
+            Some(updated_ancestors(&blob, height)?),
+            None,
+        ),
+        Err(e) => (None, None, None, Some(format!("{:?}", e))),
+    };
+
+    Ok(Report {
+        transactions: blob.transactions.len(),
+        accounts: blob.accounts.len(),
+        touched: touched.len(),
+        bytes: Bytes {
+            total: bytes.len(),
+            transactions,
+            offsets,
+            leaves: leaves * 32,
+            siblings: siblings * 32,
+        },
+        siblings: Siblings {
+            total: siblings,
+            zero,
+        },
+        hashes: Hashes {
+            pre_state: root_hashes,
+            post_state: post_hashes,
+            updated,
+        },
+        pre_state: format!("0x{}", hex::encode(pre_state)),
+        post_state,
+        rejected,
+    })
+}
+
+/// Returns the number of nodes above the nonces and values which are changed by the blob's
+/// transactions.
+fn updated_ancestors(blob: &Blob, height: usize) -> Result<usize, Error> {
+    let before = SparseMerkleTree::from_accounts(blob.accounts.clone(), height)?;
+
+    let mut after = before.clone();
+    process_transactions(&mut after, &blob.transactions).map_err(Error::State)?;
+
+    let mut ancestors: HashSet<U512> = HashSet::new();
+
+    for account in &blob.accounts {
+        let (old, new) = match (before.get(&account.0), after.get(&account.0)) {
+            (Some(old), Some(new)) => (old, new),
+            _ => continue,
+        };
+
+        let root = (U512::one() << height) + U512::from(account.0);
+        let mut updated = vec![];
+
+        if old.nonce != new.nonce {
+            updated.push((root << 2) + U512::from(1));
+        }
+
+        if old.value != new.value {
+            updated.push((root << 2) + U512::from(2));
+        }
+
+        for leaf in updated {
+            let mut index = leaf >> 1;
+            while !index.is_zero() && ancestors.insert(index) {
+                index = index >> 1;
+            }
+        }
+    }
+
+    Ok(ancestors.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::accounts::random_accounts;
+    use crate::blob::BlobBuilder;
+    use crate::workload::Workload;
+    use sheth::transaction::Transfer;
+
+    #[test]
+    fn analyze_transfer() {
+        let accounts = random_accounts(2, 8).unwrap();
+        let (from, to) = (&accounts[0], &accounts[1]);
+
+        let tx = Transaction::Transfer(Transfer {
+            to: to.0.into(),
+            from: from.0.into(),
+            nonce: from.1.nonce,
+            amount: 1,
+            signature: [0u8; 96],
+        });
+
+        let (blob, pre_state, post_state) = BlobBuilder::new(8)
+            .accounts(accounts.clone())
+            .transaction(tx)
+            .build()
+            .unwrap();
+
+        let bytes = blob.to_bytes();
+        let report = analyze(&bytes, 8).unwrap();
+
+        assert_eq!(report.transactions, 1);
+        assert_eq!(report.accounts, 2);
+        assert_eq!(report.touched, 2);
+        assert_eq!(report.bytes.transactions, 180);
+        assert_eq!(report.bytes.leaves, 10 * 32);
+        assert_eq!(
+            report.bytes.transactions
+                + report.bytes.offsets
+                + report.bytes.leaves
+                + report.bytes.siblings,
+            bytes.len()
+        );
+        assert_eq!(report.pre_state, format!("0x{}", hex::encode(pre_state)));
+        assert_eq!(
+            report.post_state,
+            Some(format!("0x{}", hex::encode(post_state)))
+        );
+
+        // The two accounts share the nodes above their lowest common ancestor
+        let common = (U512::from(accounts[0].0) ^ U512::from(accounts[1].0)).bits();
+        let ancestors = (8 - common + 1) + 2 * (common - 1) + 5;
+        assert_eq!(report.hashes.updated, Some(ancestors));
+        assert_eq!(report.hashes.pre_state, (bytes.len() - 180) / 40 - 1);
+        assert_eq!(report.hashes.post_state, Some(report.hashes.pre_state));
+    }
+
+    #[test]
+    fn analyze_rejected_block() {
+        let workload = Workload {
+            invalid_rate: 1.0,
+            invalid: vec![crate::workload::Invalid::Overdraft],
+            ..Workload::default()
+        };
+
+        let accounts = random_accounts(3, 8).unwrap();
+        let blob = crate::blob::generate_from_accounts(accounts, 2, 8, &workload);
+        let report = analyze(&blob.to_bytes(), 8).unwrap();
+
+        assert_eq!(report.post_state, None);
+        assert_eq!(report.hashes.post_state, None);
+        assert_eq!(report.hashes.updated, None);
+        assert_eq!(report.rejected, Some("Overflow".to_string()));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["bytes"]["total"], blob.to_bytes().len());
+        assert!(json["hashes"]["post_state"].is_null());
+    }
+}
@@ -1,11 +1,18 @@
-use crate::accounts::{random_accounts, AddressedAccount};
-use crate::proof::offsets::calculate as calculate_offsets;
+use crate::accounts::{generate_accounts, AddressedAccount};
+use crate::error::Error as ComposerError;
+use crate::genesis::MAX_HEIGHT;
+use crate::inspect::{inspect, Inspection};
 use crate::proof::uncompressed::generate as generate_uncompressed_proof;
+use crate::smt::SparseMerkleTree;
 use crate::transactions;
+use crate::workload::Workload;
+use bigint::U256;
 use imp::Imp;
+use sheth::error::Error;
 use sheth::process::process_transactions;
 use sheth::transaction::Transaction;
 use sheth::u264::U264;
+use std::collections::HashSet;
 
 /// A `Blob` includes all the neccessary data to construct the input data blob to `sheth`.
 #[derive(Clone)]
@@ -22,27 +29,208 @@ impl Blob {
         ret.extend(&self.proof);
         ret
     }
+
+    /// Decodes a serialized blob from a tree of height `tree_height`. The accounts are those whose
+    /// leaves are all included in the proof.
+    pub fn from_bytes(bytes: &[u8], tree_height: usize) -> Result<Blob, ComposerError> {
+        Ok(Blob::from_inspection(bytes, &inspect(bytes, tree_height)?))
+    }
+
+    /// Builds the blob `bytes` from its `inspection`, for callers which already decoded it.
+    pub(crate) fn from_inspection(bytes: &[u8], inspection: &Inspection) -> Blob {
+        Blob {
+            proof: bytes[4 + inspection.transactions.len() * 176..].to_vec(),
+            accounts: inspection.accounts(),
+            transactions: inspection.transactions.clone(),
+        }
+    }
 }
 
-/// Build a blob with specified tree height, accounts, and transactions.
-pub fn generate(accounts: usize, transactions: usize, tree_height: usize) -> Blob {
-    let accounts = random_accounts(accounts, tree_height);
-    let proof = generate_uncompressed_proof(accounts.clone(), tree_height);
-    let offsets = calculate_offsets(proof.indexes);
-    let transactions = transactions::generate(transactions, accounts.clone());
+/// Builds a blob from explicit accounts and transactions, e.g.:
+///
+/// ```ignore
+/// let (blob, pre_state, post_state) = BlobBuilder::new(256)
+///     .account(AddressedAccount(address, account))
+///     .transaction(transfer)
+///     .build()?;
+/// ```
+///
+/// The proof contains every account, so each address touched by a transaction must belong to one
+/// of the accounts.
+pub struct BlobBuilder {
+    tree_height: usize,
+    accounts: Vec<AddressedAccount>,
+    transactions: Vec<Transaction>,
+}
+
+impl BlobBuilder {
+    pub fn new(tree_height: usize) -> Self {
+        BlobBuilder {
+            tree_height,
+            accounts: vec![],
+            transactions: vec![],
+        }
+    }
+
+    pub fn account(mut self, account: AddressedAccount) -> Self {
+        self.accounts.push(account);
+        self
+    }
+
+    pub fn accounts(mut self, accounts: Vec<AddressedAccount>) -> Self {
+        self.accounts.extend(accounts);
+        self
+    }
+
+    pub fn transaction(mut self, transaction: Transaction) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    pub fn transactions(mut self, transactions: Vec<Transaction>) -> Self {
+        self.transactions.extend(transactions);
+        self
+    }
+
+    /// Returns the blob along with its pre-state root and post-state root. Fails if the accounts
+    /// don't fit in the tree, if a transaction touches an address which isn't in the proof, or if
+    /// the transactions are rejected.
+    pub fn build(self) -> Result<(Blob, [u8; 32], [u8; 32]), ComposerError> {
+        if self.tree_height == 0 || self.tree_height > MAX_HEIGHT {
+            return Err(ComposerError::HeightOutOfRange(self.tree_height));
+        }
+
+        // Building the tree checks that each address is in range and is only used once.
+        SparseMerkleTree::from_accounts(self.accounts.clone(), self.tree_height)?;
+
+        let known: HashSet<U256> = self.accounts.iter().map(|a| a.0).collect();
+
+        for tx in &self.transactions {
+            let (to, from) = match tx {
+                Transaction::Transfer(t) => (t.to, t.from),
+                _ => return Err(ComposerError::TransactionUnsupported),
+            };
+
+            for address in [to, from].iter() {
+                let address = U256::from(<[u8; 32]>::from(*address));
+
+                if !known.contains(&address) {
+                    return Err(ComposerError::AddressNotInProof(address));
+                }
+            }
+        }
+
+        let proof = generate_uncompressed_proof(self.accounts.clone(), self.tree_height);
+
+        let blob = Blob {
+            proof: proof.to_bytes(),
+            transactions: self.transactions,
+            accounts: self.accounts,
+        };
+
+        let (pre_state, post_state) = process(&blob, self.tree_height);
+        let post_state = post_state.map_err(ComposerError::State)?;
+
+        Ok((blob, pre_state, post_state))
+    }
+}
+
+/// Build a blob with specified tree height, accounts, and transactions. Fails if the tree can't
+/// hold the requested number of accounts.
+pub fn generate(
+    accounts: usize,
+    transactions: usize,
+    tree_height: usize,
+) -> Result<Blob, ComposerError> {
+    generate_with_workload(accounts, transactions, tree_height, &Workload::default())
+}
+
+/// Build a blob with random accounts and transactions that are shaped by `workload`. The accounts'
+/// addresses are assigned according to `workload.addresses`.
+pub fn generate_with_workload(
+    accounts: usize,
+    transactions: usize,
+    tree_height: usize,
+    workload: &Workload,
+) -> Result<Blob, ComposerError> {
+    let accounts = generate_accounts(accounts, tree_height, workload.seed, &workload.addresses)?;
+
+    Ok(generate_from_accounts(
+        accounts,
+        transactions,
+        tree_height,
+        workload,
+    ))
+}
+
+/// Build a blob with random transactions between the specified accounts. If `workload` injects
+/// invalid transactions, the accounts may first be adjusted by `Workload::prepare`.
+pub fn generate_from_accounts(
+    mut accounts: Vec<AddressedAccount>,
+    transactions: usize,
+    tree_height: usize,
+    workload: &Workload,
+) -> Blob {
+    workload.prepare(&mut accounts);
+    build(accounts, transactions, tree_height, workload)
+}
+
+/// A blob along with its pre-state root, and either its post-state root or the error which causes
+/// it to be rejected.
+pub type ChainedBlob = (Blob, [u8; 32], Result<[u8; 32], Error>);
+
+/// Builds `blocks` consecutive blobs, where each blob spends the state left by the previous one.
+/// A rejected blob leaves the state untouched.
+pub fn generate_chain(
+    mut accounts: Vec<AddressedAccount>,
+    blocks: usize,
+    transactions: usize,
+    tree_height: usize,
+    workload: &Workload,
+) -> Result<Vec<ChainedBlob>, ComposerError> {
+    workload.prepare(&mut accounts);
+
+    let mut chain = vec![];
+
+    for i in 0..blocks {
+        let workload = Workload {
+            seed: workload.seed.wrapping_add(i as u64),
+            ..workload.clone()
+        };
+
+        let blob = build(accounts.clone(), transactions, tree_height, &workload);
+        let (pre_state, post_state) = process(&blob, tree_height);
+
+        if post_state.is_ok() {
+            let mut tree = SparseMerkleTree::from_accounts(accounts.clone(), tree_height)?;
+            process_transactions(&mut tree, &blob.transactions).map_err(ComposerError::State)?;
+
+            for account in accounts.iter_mut() {
+                account.1 = tree
+                    .get(&account.0)
+                    .cloned()
+                    .ok_or(ComposerError::AccountUnknown(account.0))?;
+            }
+        }
 
-    let mut compressed_proof = offsets.iter().fold(vec![], |mut acc, x| {
-        acc.extend(&x.to_le_bytes());
-        acc
-    });
+        chain.push((blob, pre_state, post_state));
+    }
 
-    compressed_proof = proof.values.iter().fold(compressed_proof, |mut acc, x| {
-        acc.extend(x.as_bytes());
-        acc
-    });
+    Ok(chain)
+}
+
+fn build(
+    accounts: Vec<AddressedAccount>,
+    transactions: usize,
+    tree_height: usize,
+    workload: &Workload,
+) -> Blob {
+    let proof = generate_uncompressed_proof(accounts.clone(), tree_height);
+    let transactions =
+        transactions::generate_with_workload(transactions, accounts.clone(), workload);
 
     Blob {
-        proof: compressed_proof,
+        proof: proof.to_bytes(),
         transactions,
         accounts,
     }
@@ -53,23 +241,39 @@ pub fn generate_with_roots(
     accounts: usize,
     transactions: usize,
     tree_height: usize,
-) -> (Blob, [u8; 32], [u8; 32]) {
-    let mut blob = generate(accounts, transactions, tree_height);
-    let ret_blob = blob.clone();
+) -> Result<(Blob, [u8; 32], [u8; 32]), ComposerError> {
+    let blob = generate(accounts, transactions, tree_height)?;
+    let (pre_state, post_state) = roots(&blob, tree_height);
+
+    Ok((blob, pre_state, post_state))
+}
+
+/// Returns the pre-state root and the post-state root of the blob. Panics if the blob's
+/// transactions are rejected.
+pub fn roots(blob: &Blob, tree_height: usize) -> ([u8; 32], [u8; 32]) {
+    let (pre_state, post_state) = process(blob, tree_height);
+    (pre_state, post_state.expect("Transactions to be valid"))
+}
 
-    let mut mem = Imp::<U264>::new(&mut blob.proof, tree_height + 3);
+/// Returns the pre-state root of the blob, and either the post-state root or the error which
+/// causes the blob to be rejected.
+pub fn process(blob: &Blob, tree_height: usize) -> ([u8; 32], Result<[u8; 32], Error>) {
+    let mut proof = blob.proof.clone();
+    let mut mem = Imp::<U264>::new(&mut proof, tree_height + 3);
 
     let pre_state = mem.root();
-    assert_eq!(process_transactions(&mut mem, &blob.transactions), Ok(()));
-    let post_state = mem.root();
+    let post_state = process_transactions(&mut mem, &blob.transactions).map(|_| mem.root());
 
-    (ret_blob, pre_state, post_state)
+    (pre_state, post_state)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::accounts::{random_accounts, Addresses};
+    use crate::workload::Invalid;
     use arrayref::array_ref;
+    use sheth::transaction::Transfer;
 
     #[test]
     fn generate_small_tree() {
@@ -93,8 +297,179 @@ mod test {
             211, 52, 50, 189, 96, 107, 228, 122, 11, 68, 182, 28,
         ];
 
-        assert_eq!(generate(1, 0, 1).to_bytes(), proof);
+        assert_eq!(generate(1, 0, 1).unwrap().to_bytes(), proof);
         let mut mem = Imp::<U264>::new(&mut proof[4..], 4);
         assert_eq!(mem.root(), *array_ref![root, 0, 32]);
     }
+
+    fn transfer(to: U256, from: &AddressedAccount, amount: u64) -> Transaction {
+        Transaction::Transfer(Transfer {
+            to: to.into(),
+            from: from.0.into(),
+            nonce: from.1.nonce,
+            amount,
+            signature: [0u8; 96],
+        })
+    }
+
+    #[test]
+    fn build_from_explicit_transactions() {
+        let accounts = random_accounts(3, 16).unwrap();
+        let transactions = vec![
+            transfer(accounts[1].0, &accounts[0], 1),
+            transfer(accounts[0].0, &accounts[2], 2),
+        ];
+
+        let (blob, pre_state, post_state) = BlobBuilder::new(16)
+            .accounts(accounts.clone())
+            .transactions(transactions.clone())
+            .build()
+            .unwrap();
+
+        let mut tree = SparseMerkleTree::from_accounts(accounts.clone(), 16).unwrap();
+        assert_eq!(pre_state, tree.root());
+        assert_eq!(process_transactions(&mut tree, &transactions), Ok(()));
+        assert_eq!(post_state, tree.root());
+        assert_eq!(blob.transactions.len(), 2);
+
+        let (same, _, _) = BlobBuilder::new(16)
+            .account(accounts[0].clone())
+            .account(accounts[1].clone())
+            .account(accounts[2].clone())
+            .transaction(transactions[0].clone())
+            .transaction(transactions[1].clone())
+            .build()
+            .unwrap();
+        assert_eq!(same.to_bytes(), blob.to_bytes());
+    }
+
+    #[test]
+    fn build_rejects_unknown_addresses() {
+        let accounts = random_accounts(2, 16).unwrap();
+        let unknown = accounts[0].0 ^ U256::one();
+
+        let result = BlobBuilder::new(16)
+            .accounts(accounts.clone())
+            .transaction(transfer(unknown, &accounts[0], 1))
+            .build();
+        assert_eq!(
+            result.err(),
+            Some(ComposerError::AddressNotInProof(unknown))
+        );
+
+        let result = BlobBuilder::new(16)
+            .accounts(accounts.clone())
+            .transaction(transfer(accounts[1].0, &accounts[0], u64::MAX))
+            .build();
+        assert_eq!(result.err(), Some(ComposerError::State(Error::Overflow)));
+
+        let result = BlobBuilder::new(16)
+            .account(accounts[0].clone())
+            .account(accounts[0].clone())
+            .build();
+        assert_eq!(
+            result.err(),
+            Some(ComposerError::AccountExists(accounts[0].0))
+        );
+    }
+
+    #[test]
+    fn chained_blobs() {
+        let accounts = random_accounts(4, 16).unwrap();
+        let chain = generate_chain(accounts, 3, 5, 16, &Workload::default()).unwrap();
+
+        assert_eq!(chain.len(), 3);
+        for pair in chain.windows(2) {
+            assert_eq!(pair[0].2, Ok(pair[1].1));
+        }
+
+        // The seed changes with each block
+        assert_ne!(
+            transactions::serialize(&chain[0].0.transactions),
+            transactions::serialize(&chain[1].0.transactions)
+        );
+
+        let workload = Workload {
+            invalid_rate: 1.0,
+            invalid: vec![Invalid::Overdraft],
+            ..Workload::default()
+        };
+
+        let accounts = random_accounts(4, 16).unwrap();
+        let chain = generate_chain(accounts, 2, 5, 16, &workload).unwrap();
+        assert!(chain[0].2.is_err());
+        assert_eq!(chain[0].1, chain[1].1);
+    }
+
+    #[test]
+    fn generate_with_addresses() {
+        let workload = Workload {
+            addresses: Addresses::Sequential,
+            ..Workload::default()
+        };
+
+        let blob = generate_with_workload(4, 2, 2, &workload).unwrap();
+        let addresses: Vec<U256> = blob.accounts.iter().map(|a| a.0).collect();
+        assert_eq!(addresses, vec![0.into(), 1.into(), 2.into(), 3.into()]);
+
+        assert_eq!(
+            generate_with_workload(5, 2, 2, &workload).err(),
+            Some(ComposerError::CapacityExceeded(5))
+        );
+    }
+
+    #[test]
+    fn from_bytes_round_trip() {
+        let blob = generate(4, 3, 16).unwrap();
+        let decoded = Blob::from_bytes(&blob.to_bytes(), 16).unwrap();
+
+        assert_eq!(decoded.to_bytes(), blob.to_bytes());
+        assert_eq!(decoded.accounts.len(), 4);
+        assert!(Blob::from_bytes(&blob.to_bytes()[1..], 16).is_err());
+    }
+
+    fn invalid(kind: Invalid) -> ([u8; 32], Result<[u8; 32], Error>) {
+        let workload = Workload {
+            invalid_rate: 1.0,
+            invalid: vec![kind],
+            ..Workload::default()
+        };
+
+        process(&generate_with_workload(10, 20, 16, &workload).unwrap(), 16)
+    }
+
+    #[test]
+    fn invalid_transactions() {
+        let (pre_state, post_state) = invalid(Invalid::Nonce);
+        assert_eq!(post_state, Ok(pre_state));
+
+        assert_eq!(invalid(Invalid::Overdraft).1, Err(Error::Overflow));
+        assert_eq!(invalid(Invalid::Overflow).1, Err(Error::Overflow));
+
+        match invalid(Invalid::UnknownAddress).1 {
+            Err(Error::StateIncomplete(_)) => (),
+            _ => panic!("expected unknown address"),
+        }
+
+        // Signatures aren't verified yet, so the transactions are applied.
+        let (pre_state, post_state) = invalid(Invalid::Signature);
+        assert_ne!(post_state, Ok(pre_state));
+    }
+
+    #[test]
+    fn some_invalid_transactions() {
+        let workload = Workload {
+            invalid_rate: 0.2,
+            invalid: vec![Invalid::Nonce, Invalid::Signature],
+            ..Workload::default()
+        };
+
+        let blob = generate_with_workload(10, 50, 16, &workload).unwrap();
+        let (pre_state, post_state) = process(&blob, 16);
+
+        let mut tree = SparseMerkleTree::from_accounts(blob.accounts.clone(), 16).unwrap();
+        assert_eq!(tree.root(), pre_state);
+        assert_eq!(process_transactions(&mut tree, &blob.transactions), Ok(()));
+        assert_eq!(post_state, Ok(tree.root()));
+    }
 }
@@ -0,0 +1,576 @@
+use crate::accounts::AddressedAccount;
+use crate::error::Error;
+use crate::proof::h256::H256;
+use crate::proof::uncompressed::UncompressedProof;
+use crate::smt::SparseMerkleTree;
+use arrayref::array_ref;
+use bigint::{U256, U512};
+use sheth::account::Account;
+use sheth::bls::PublicKey;
+use sheth::process::process_transactions;
+use sheth::transaction::Transaction;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"shethdb1";
+
+/// Number of blocks appended to the log before they're compacted into a new snapshot.
+const COMPACT_AFTER: u64 = 64;
+
+/// A file backed store of the full state.
+///
+/// The accounts, the cached nodes of the tree, and the state root of every committed block are
+/// written to a snapshot file. Each commit only appends the accounts changed by its block to a log
+/// next to the snapshot, so its cost doesn't grow with the state. Every `COMPACT_AFTER` blocks,
+/// the log is folded into a new snapshot, which is written next to the old one and renamed into
+/// place.
+pub struct Database {
+    path: PathBuf,
+    tree: SparseMerkleTree,
+    roots: BTreeMap<u64, [u8; 32]>,
+
+    /// Number of blocks in the log which aren't part of the snapshot yet
+    logged: u64,
+}
+
+impl Database {
+    /// Creates a new database at `path` whose genesis (block `0`) state contains `accounts`.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        accounts: Vec<AddressedAccount>,
+        height: usize,
+    ) -> Result<Self, Error> {
+        let tree = SparseMerkleTree::from_accounts(accounts, height)?;
+
+        let mut roots = BTreeMap::new();
+        roots.insert(0, tree.root());
+
+        let mut db = Database {
+            path: path.as_ref().to_path_buf(),
+            tree,
+            roots,
+            logged: 0,
+        };
+
+        db.compact()?;
+        Ok(db)
+    }
+
+    /// Opens an existing database and replays the blocks in its log. The accounts are checked
+    /// against the cached account roots, but the nodes above them are loaded as-is.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = fs::read(path.as_ref())?;
+        let mut reader = Reader::new(&bytes);
+
+        if reader.bytes(8)? != MAGIC {
+            return Err(Error::Corrupt("unknown file format".to_string()));
+        }
+
+        let height = reader.u64()? as usize;
+
+        let mut roots = BTreeMap::new();
+        for _ in 0..reader.u64()? {
+            let block = reader.u64()?;
+            roots.insert(block, *array_ref![reader.bytes(32)?, 0, 32]);
+        }
+
+        let mut accounts = BTreeMap::new();
+        for _ in 0..reader.u64()? {
+            let AddressedAccount(address, account) = reader.account()?;
+            accounts.insert(address, account);
+        }
+
+        let mut nodes = HashMap::new();
+        for _ in 0..reader.u64()? {
+            let index = U512::from_big_endian(reader.bytes(64)?);
+            nodes.insert(index, H256::new(array_ref![reader.bytes(32)?, 0, 32]));
+        }
+
+        let tree = SparseMerkleTree::from_parts(height, accounts, nodes);
+        if !tree.leaves_match() {
+            return Err(Error::Corrupt(
+                "accounts don't match the cached nodes".to_string(),
+            ));
+        }
+
+        match roots.values().next_back() {
+            Some(root) if *root == tree.root() => (),
+            _ => return Err(Error::Corrupt("state root mismatch".to_string())),
+        }
+
+        let mut db = Database {
+            path: path.as_ref().to_path_buf(),
+            tree,
+            roots,
+            logged: 0,
+        };
+
+        db.replay()?;
+        Ok(db)
+    }
+
+    /// Returns the full state as of the latest block.
+    pub fn tree(&self) -> &SparseMerkleTree {
+        &self.tree
+    }
+
+    /// Returns the number of the latest committed block.
+    pub fn block(&self) -> u64 {
+        *self.roots.keys().next_back().unwrap_or(&0)
+    }
+
+    /// Returns the state root after the latest committed block.
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    /// Returns the state root after block `block`, if it has been committed.
+    pub fn root_at(&self, block: u64) -> Option<[u8; 32]> {
+        self.roots.get(&block).cloned()
+    }
+
+    /// Returns a multiproof for `addresses` against the latest state root.
+    pub fn proof(&self, addresses: &[U256]) -> Result<UncompressedProof, Error> {
+        self.tree.proof(addresses)
+    }
+
+    /// Processes `transactions` as the next block and commits the resulting state.
+    pub fn apply_block(&mut self, transactions: &[Transaction]) -> Result<[u8; 32], Error> {
+        let mut tree = self.tree.clone();
+        process_transactions(&mut tree, transactions).map_err(Error::State)?;
+
+        let touched = transactions
+            .iter()
+            .flat_map(|tx| match tx {
+                Transaction::Transfer(t) => vec![t.from, t.to],
+                _ => vec![tx.from()],
+            })
+            .map(|address| U256::from(<[u8; 32]>::from(address)))
+            .collect();
+
+        self.commit(tree, touched)
+    }
+
+    /// Writes `accounts` to the state, inserting any which don't exist yet, and commits the result
+    /// as the next block.
+    pub fn apply_updates(&mut self, accounts: Vec<AddressedAccount>) -> Result<[u8; 32], Error> {
+        let mut tree = self.tree.clone();
+        let touched = accounts.iter().map(|a| a.0).collect();
+
+        for AddressedAccount(address, account) in accounts {
+            if tree.get(&address).is_some() {
+                tree.update(address, account)?;
+            } else {
+                tree.insert(address, account)?;
+            }
+        }
+
+        self.commit(tree, touched)
+    }
+
+    /// Writes the full state to a new snapshot and empties the log. This happens every
+    /// `COMPACT_AFTER` blocks, but may be done earlier, e.g. before shutting down.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&(self.tree.height() as u64).to_le_bytes());
+
+        bytes.extend(&(self.roots.len() as u64).to_le_bytes());
+        for (block, root) in &self.roots {
+            bytes.extend(&block.to_le_bytes());
+            bytes.extend(root);
+        }
+
+        bytes.extend(&(self.tree.len() as u64).to_le_bytes());
+        for account in self.tree.accounts() {
+            write_account(&mut bytes, &account);
+        }
+
+        bytes.extend(&(self.tree.nodes().len() as u64).to_le_bytes());
+        for (index, node) in self.tree.nodes() {
+            let mut buf = [0u8; 64];
+            index.to_big_endian(&mut buf);
+
+            bytes.extend(&buf[..]);
+            bytes.extend(node.as_bytes());
+        }
+
+        // Write the snapshot to a temporary file first, so that a crash can't leave behind a
+        // partially written state. Until the log is removed, its blocks are skipped when it's
+        // replayed, since they're already part of the snapshot.
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        if self.log_path().exists() {
+            fs::remove_file(self.log_path())?;
+        }
+
+        self.logged = 0;
+        Ok(())
+    }
+
+    /// Appends the block which results in `tree` to the log, where `touched` are the addresses of
+    /// the accounts it changed.
+    fn commit(&mut self, tree: SparseMerkleTree, touched: Vec<U256>) -> Result<[u8; 32], Error> {
+        let block = self.block() + 1;
+        let root = tree.root();
+
+        let touched: BTreeSet<U256> = touched.into_iter().collect();
+        let accounts: Vec<AddressedAccount> = touched
+            .into_iter()
+            .filter_map(|address| {
+                tree.get(&address)
+                    .map(|account| AddressedAccount(address, account.clone()))
+            })
+            .collect();
+
+        let mut record = block.to_le_bytes().to_vec();
+        record.extend(&root);
+        record.extend(&(accounts.len() as u64).to_le_bytes());
+        for account in &accounts {
+            write_account(&mut record, account);
+        }
+
+        // Each record is prefixed with its length, so a record which was only partially written
+        // before a crash is detected and dropped when the log is replayed
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())?;
+        file.write_all(&(record.len() as u64).to_le_bytes())?;
+        file.write_all(&record)?;
+        file.sync_data()?;
+
+        self.tree = tree;
+        self.roots.insert(block, root);
+        self.logged += 1;
+
+        if self.logged >= COMPACT_AFTER {
+            self.compact()?;
+        }
+
+        Ok(root)
+    }
+
+    /// Applies the blocks in the log which are newer than the snapshot. A partially written
+    /// record at the end of the log is truncated.
+    fn replay(&mut self) -> Result<(), Error> {
+        let bytes = match fs::read(self.log_path()) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut reader = Reader::new(&bytes);
+        let mut end = 0;
+
+        while let Ok(record) = reader.u64().and_then(|len| reader.bytes(len as usize)) {
+            let mut record = Reader::new(record);
+            let block = record.u64()?;
+            let root = *array_ref![record.bytes(32)?, 0, 32];
+
+            let mut accounts = vec![];
+            for _ in 0..record.u64()? {
+                accounts.push(record.account()?);
+            }
+
+            end = reader.position;
+
+            // Blocks up to the snapshot's were compacted, but the log wasn't removed yet
+            if block <= self.block() {
+                continue;
+            }
+
+            if block != self.block() + 1 {
+                return Err(Error::Corrupt(format!("log skips to block {}", block)));
+            }
+
+            for AddressedAccount(address, account) in accounts {
+                if self.tree.get(&address).is_some() {
+                    self.tree.update(address, account)?;
+                } else {
+                    self.tree.insert(address, account)?;
+                }
+            }
+
+            if self.tree.root() != root {
+                return Err(Error::Corrupt(format!(
+                    "log root mismatch at block {}",
+                    block
+                )));
+            }
+
+            self.roots.insert(block, root);
+            self.logged += 1;
+        }
+
+        if end < bytes.len() {
+            OpenOptions::new()
+                .write(true)
+                .open(self.log_path())?
+                .set_len(end as u64)?;
+        }
+
+        Ok(())
+    }
+
+    fn log_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".log");
+        path.into()
+    }
+}
+
+fn write_account(bytes: &mut Vec<u8>, AddressedAccount(address, account): &AddressedAccount) {
+    let mut buf = [0u8; 32];
+    address.to_big_endian(&mut buf);
+
+    bytes.extend(&buf);
+    bytes.extend(&account.pubkey.as_bytes()[..]);
+    bytes.extend(&account.nonce.to_le_bytes());
+    bytes.extend(&account.value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() - self.position < n {
+            return Err(Error::Corrupt("unexpected end of file".to_string()));
+        }
+
+        self.position += n;
+        Ok(&self.bytes[self.position - n..self.position])
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(*array_ref![self.bytes(8)?, 0, 8]))
+    }
+
+    fn account(&mut self) -> Result<AddressedAccount, Error> {
+        let address = U256::from_big_endian(self.bytes(32)?);
+        let pubkey = PublicKey::new(*array_ref![self.bytes(48)?, 0, 48]);
+        let nonce = self.u64()?;
+        let value = self.u64()?;
+
+        Ok(AddressedAccount(
+            address,
+            Account {
+                pubkey,
+                nonce,
+                value,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::accounts::random_accounts;
+    use crate::transactions;
+    use sheth::transaction::Transfer;
+
+    fn path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("composer-db-{}-{}", name, std::process::id()));
+        path
+    }
+
+    /// Removes the snapshot at `path` and its log, if any.
+    fn remove(path: &Path) {
+        fs::remove_file(path).unwrap();
+
+        let mut log = path.to_path_buf().into_os_string();
+        log.push(".log");
+        let _ = fs::remove_file(log);
+    }
+
+    /// Returns an update of the first account which sets its value to `value`.
+    fn update(accounts: &[AddressedAccount], value: u64) -> Vec<AddressedAccount> {
+        let mut account = accounts[0].1.clone();
+        account.value = value;
+        vec![AddressedAccount(accounts[0].0, account)]
+    }
+
+    #[test]
+    fn reopen_keeps_state() {
+        let path = path("reopen");
+        let accounts = random_accounts(10, 32).unwrap();
+        let transactions = transactions::generate(5, accounts.clone());
+
+        let mut db = Database::create(&path, accounts, 32).unwrap();
+        let genesis = db.root();
+        let root = db.apply_block(&transactions).unwrap();
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.block(), 1);
+        assert_eq!(db.root(), root);
+        assert_eq!(db.root_at(0), Some(genesis));
+        assert_eq!(db.root_at(1), Some(root));
+        assert_eq!(db.tree().len(), 10);
+
+        remove(&path);
+    }
+
+    #[test]
+    fn failed_block_is_not_committed() {
+        let path = path("failed");
+        let accounts = random_accounts(2, 16).unwrap();
+
+        let mut db = Database::create(&path, accounts.clone(), 16).unwrap();
+        let root = db.root();
+
+        let mut rich = accounts[1].1.clone();
+        rich.value = u64::MAX;
+        db.apply_updates(vec![AddressedAccount(accounts[1].0, rich)])
+            .unwrap();
+
+        let overflow = vec![Transaction::Transfer(Transfer {
+            to: accounts[1].0.into(),
+            from: accounts[0].0.into(),
+            nonce: accounts[0].1.nonce,
+            amount: 1,
+            signature: [0u8; 96],
+        })];
+
+        assert_eq!(
+            db.apply_block(&overflow),
+            Err(Error::State(sheth::error::Error::Overflow))
+        );
+        assert_eq!(db.block(), 1);
+        assert_ne!(db.root(), root);
+
+        let reopened = Database::open(&path).unwrap();
+        assert_eq!(reopened.block(), 1);
+        assert_eq!(reopened.root(), db.root());
+
+        remove(&path);
+    }
+
+    #[test]
+    fn corrupt_file_is_rejected() {
+        let path = path("corrupt");
+        Database::create(&path, random_accounts(1, 8).unwrap(), 8).unwrap();
+
+        // Truncated snapshot
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Database::open(&path).is_err());
+
+        // Stored root doesn't match the cached nodes
+        let mut corrupt = bytes.clone();
+        corrupt[32] ^= 1;
+        fs::write(&path, &corrupt).unwrap();
+        assert!(Database::open(&path).is_err());
+
+        // Account value doesn't match its cached root. The value follows the magic, height, one
+        // root, the account count, and the account's address, pubkey and nonce.
+        let mut corrupt = bytes.clone();
+        corrupt[8 + 8 + 8 + 40 + 8 + 32 + 48 + 8] ^= 1;
+        fs::write(&path, &corrupt).unwrap();
+        assert_eq!(
+            Database::open(&path).err(),
+            Some(Error::Corrupt(
+                "accounts don't match the cached nodes".to_string()
+            ))
+        );
+
+        remove(&path);
+    }
+
+    #[test]
+    fn commits_append_to_the_log() {
+        let path = path("log");
+        let accounts = random_accounts(10, 32).unwrap();
+
+        let mut db = Database::create(&path, accounts.clone(), 32).unwrap();
+        let snapshot = fs::read(&path).unwrap();
+
+        for value in 1..4 {
+            db.apply_updates(update(&accounts, value)).unwrap();
+        }
+
+        // Only the log grows
+        assert_eq!(fs::read(&path).unwrap(), snapshot);
+        assert!(db.log_path().exists());
+
+        let reopened = Database::open(&path).unwrap();
+        assert_eq!(reopened.block(), 3);
+        assert_eq!(reopened.root(), db.root());
+        assert_eq!(reopened.root_at(2), db.root_at(2));
+        assert_eq!(reopened.tree().get(&accounts[0].0).unwrap().value, 3);
+
+        remove(&path);
+    }
+
+    #[test]
+    fn log_is_compacted() {
+        let path = path("compact");
+        let accounts = random_accounts(4, 16).unwrap();
+
+        let mut db = Database::create(&path, accounts.clone(), 16).unwrap();
+        for value in 0..COMPACT_AFTER {
+            db.apply_updates(update(&accounts, value)).unwrap();
+        }
+
+        assert!(!db.log_path().exists());
+
+        db.apply_updates(update(&accounts, 1)).unwrap();
+
+        let reopened = Database::open(&path).unwrap();
+        assert_eq!(reopened.block(), COMPACT_AFTER + 1);
+        assert_eq!(reopened.root(), db.root());
+
+        remove(&path);
+    }
+
+    #[test]
+    fn partial_log_record_is_dropped() {
+        let path = path("partial");
+        let accounts = random_accounts(4, 16).unwrap();
+
+        let mut db = Database::create(&path, accounts.clone(), 16).unwrap();
+        db.apply_updates(update(&accounts, 1)).unwrap();
+        db.apply_updates(update(&accounts, 2)).unwrap();
+
+        let log = fs::read(db.log_path()).unwrap();
+        fs::write(db.log_path(), &log[..log.len() - 1]).unwrap();
+
+        let mut reopened = Database::open(&path).unwrap();
+        assert_eq!(reopened.block(), 1);
+        assert_eq!(reopened.root(), db.root_at(1).unwrap());
+
+        // The next block replaces the dropped one
+        let root = reopened.apply_updates(update(&accounts, 3)).unwrap();
+        let reopened = Database::open(&path).unwrap();
+        assert_eq!(reopened.block(), 2);
+        assert_eq!(reopened.root(), root);
+
+        // A complete record whose accounts don't match its root. The value of the record's only
+        // account follows the length, block, root, account count, and the address, pubkey and
+        // nonce.
+        let mut log = fs::read(db.log_path()).unwrap();
+        log[8 + 8 + 32 + 8 + 32 + 48 + 8] ^= 1;
+        fs::write(db.log_path(), &log).unwrap();
+        assert_eq!(
+            Database::open(&path).err(),
+            Some(Error::Corrupt("log root mismatch at block 1".to_string()))
+        );
+
+        remove(&path);
+    }
+}
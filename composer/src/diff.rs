@@ -0,0 +1,230 @@
+use crate::error::Error;
+use crate::inspect::{decode_proof, node, Chunk, Field, Node};
+use crate::proof::h256::H256;
+use crate::proof::sort::alpha_sort;
+use crate::smt::{hash_pair, SparseMerkleTree};
+use arrayref::array_ref;
+use bigint::U512;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// A node of the state tree whose value differs between two views of the state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    /// General index of the node
+    pub index: U512,
+    pub node: Node,
+    pub left: H256,
+    pub right: H256,
+}
+
+/// Compares two serialized proofs of a tree of height `height`.
+///
+/// The nodes above the chunks of each proof are calculated, so proofs of different accounts can
+/// still be compared where they overlap. Only the deepest differing nodes are reported, since each
+/// of their ancestors differs as well.
+pub fn compare_proofs(left: &[u8], right: &[u8], height: usize) -> Result<Vec<Difference>, Error> {
+    let (_, left) = decode_proof(left, height)?;
+    let (_, right) = decode_proof(right, height)?;
+
+    let right = expand(&right);
+
+    Ok(differences(
+        &expand(&left),
+        |index| right.get(index).cloned(),
+        height,
+    ))
+}
+
+/// Compares a serialized proof with the full state, e.g. to find which accounts of a proof are
+/// out of date.
+pub fn compare_state(proof: &[u8], tree: &SparseMerkleTree) -> Result<Vec<Difference>, Error> {
+    let (_, chunks) = decode_proof(proof, tree.height())?;
+
+    Ok(differences(
+        &expand(&chunks),
+        |index| tree.value_at(index).ok(),
+        tree.height(),
+    ))
+}
+
+/// Returns the value of every node which is either a chunk of the proof or can be calculated from
+/// them.
+fn expand(chunks: &[Chunk]) -> HashMap<U512, H256> {
+    let mut nodes: HashMap<U512, H256> = HashMap::new();
+    let mut levels: BTreeMap<usize, Vec<U512>> = BTreeMap::new();
+
+    for chunk in chunks {
+        nodes.insert(chunk.index, chunk.value);
+        levels
+            .entry(chunk.index.bits())
+            .or_default()
+            .push(chunk.index);
+    }
+
+    while let Some((bits, level)) = levels.pop_last() {
+        for index in level {
+            let parent = index >> 1;
+
+            if parent.is_zero() || nodes.contains_key(&parent) {
+                continue;
+            }
+
+            let left = index & !U512::one();
+            let right = left + U512::one();
+
+            if let (Some(l), Some(r)) = (nodes.get(&left), nodes.get(&right)) {
+                let value = hash_pair(l, r);
+                nodes.insert(parent, value);
+                levels.entry(bits - 1).or_default().push(parent);
+            }
+        }
+    }
+
+    nodes
+}
+
+/// Returns the nodes of `left` which differ from `right`, but whose children don't.
+fn differences<F>(left: &HashMap<U512, H256>, right: F, height: usize) -> Vec<Difference>
+where
+    F: Fn(&U512) -> Option<H256>,
+{
+    let differing: HashMap<U512, H256> = left
+        .iter()
+        .filter_map(|(index, value)| match right(index) {
+            Some(other) if other != *value => Some((*index, other)),
+            _ => None,
+        })
+        .collect();
+
+    let parents: HashSet<U512> = differing.keys().map(|index| *index >> 1).collect();
+
+    let deepest = differing
+        .keys()
+        .filter(|index| !parents.contains(index))
+        .cloned()
+        .collect();
+
+    alpha_sort(&deepest)
+        .into_iter()
+        .map(|index| Difference {
+            index,
+            node: node(index, height),
+            left: left[&index],
+            right: differing[&index],
+        })
+        .collect()
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ({})", self.index, self.node)?;
+
+        for (side, value) in [("left", &self.left), ("right", &self.right)].iter() {
+            write!(f, "  {:<5} 0x{}", side, hex::encode(value.as_bytes()))?;
+
+            // Decode the integer fields, since they are hard to read as little endian bytes.
+            if let Node::Account(_, Field::Nonce) | Node::Account(_, Field::Value) = self.node {
+                write!(
+                    f,
+                    " ({})",
+                    u64::from_le_bytes(*array_ref![value.as_bytes(), 0, 8])
+                )?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::accounts::random_accounts;
+    use crate::transactions;
+    use bigint::U256;
+    use sheth::process::process_transactions;
+    use sheth::transaction::{Transaction, Transfer};
+
+    #[test]
+    fn identical_proofs() {
+        let accounts = random_accounts(4, 16).unwrap();
+        let tree = SparseMerkleTree::from_accounts(accounts.clone(), 16).unwrap();
+        let addresses: Vec<U256> = accounts.iter().map(|a| a.0).collect();
+
+        let all = tree.proof(&addresses).unwrap().to_bytes();
+        let some = tree.proof(&addresses[1..3]).unwrap().to_bytes();
+
+        assert_eq!(compare_proofs(&all, &all, 16), Ok(vec![]));
+        assert_eq!(compare_proofs(&all, &some, 16), Ok(vec![]));
+        assert_eq!(compare_state(&some, &tree), Ok(vec![]));
+
+        let txs = transactions::generate(10, accounts);
+        let mut updated = tree.clone();
+        assert_eq!(process_transactions(&mut updated, &txs), Ok(()));
+        assert!(!compare_state(&all, &updated).unwrap().is_empty());
+    }
+
+    #[test]
+    fn transfer_changes_nonce_and_values() {
+        let accounts = random_accounts(3, 16).unwrap();
+        let mut tree = SparseMerkleTree::from_accounts(accounts.clone(), 16).unwrap();
+        let addresses: Vec<U256> = accounts.iter().map(|a| a.0).collect();
+        let before = tree.proof(&addresses).unwrap().to_bytes();
+
+        let (from, to) = (&accounts[0], &accounts[1]);
+        let tx = Transaction::Transfer(Transfer {
+            to: to.0.into(),
+            from: from.0.into(),
+            nonce: from.1.nonce,
+            amount: 1,
+            signature: [0u8; 96],
+        });
+        assert_eq!(process_transactions(&mut tree, &[tx]), Ok(()));
+
+        let after = tree.proof(&addresses).unwrap().to_bytes();
+        let diff = compare_proofs(&before, &after, 16).unwrap();
+
+        let mut nodes: Vec<Node> = diff.iter().map(|d| d.node).collect();
+        let mut expected = vec![
+            Node::Account(from.0, Field::Nonce),
+            Node::Account(from.0, Field::Value),
+            Node::Account(to.0, Field::Value),
+        ];
+        nodes.sort_by_key(|n| format!("{:?}", n));
+        expected.sort_by_key(|n| format!("{:?}", n));
+        assert_eq!(nodes, expected);
+
+        let value = diff
+            .iter()
+            .find(|d| d.node == Node::Account(to.0, Field::Value))
+            .unwrap();
+        assert_eq!(
+            value.to_string().lines().nth(2).unwrap(),
+            format!(
+                "  right 0x{} ({})",
+                hex::encode(value.right.as_bytes()),
+                to.1.value + 1
+            )
+        );
+    }
+
+    #[test]
+    fn proof_and_state() {
+        let accounts = random_accounts(3, 16).unwrap();
+        let mut tree = SparseMerkleTree::from_accounts(accounts.clone(), 16).unwrap();
+        let proof = tree.proof(&[accounts[2].0]).unwrap().to_bytes();
+
+        // Updating an account which isn't in the proof changes one of its siblings.
+        let mut account = accounts[0].1.clone();
+        account.nonce += 1;
+        tree.update(accounts[0].0, account).unwrap();
+
+        let diff = compare_state(&proof, &tree).unwrap();
+        assert_eq!(diff.len(), 1);
+        assert!(matches!(diff[0].node, Node::Branch(_)));
+        assert_eq!(diff[0].right, tree.value_at(&diff[0].index).unwrap());
+    }
+}
@@ -0,0 +1,59 @@
+use bigint::{U256, U512};
+
+/// An enum of errors that can occur while composing state, proofs, and blobs.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The address does not fit into a tree of the given height
+    AddressOutOfRange(U256),
+
+    /// An account already exists at the address
+    AccountExists(U256),
+
+    /// No account exists at the address
+    AccountUnknown(U256),
+
+    /// The database could not be read or written
+    Io(String),
+
+    /// The database file is not in the expected format
+    Corrupt(String),
+
+    /// A transaction could not be applied to the state
+    State(sheth::error::Error),
+
+    /// The tree height is not between 1 and 256
+    HeightOutOfRange(usize),
+
+    /// Two accounts map to the same address
+    AddressCollision(U256),
+
+    /// The public key is not a 48 byte hex string
+    PubkeyInvalid(String),
+
+    /// The genesis file could not be parsed
+    GenesisInvalid(String),
+
+    /// The blob could not be decoded
+    BlobInvalid(String),
+
+    /// The offsets don't describe a valid multiproof
+    OffsetsInvalid(String),
+
+    /// The general index isn't a node of the state tree
+    IndexInvalid(U512),
+
+    /// There is no room for the requested number of accounts
+    CapacityExceeded(usize),
+
+    /// A transaction touches an address which isn't included in the proof
+    AddressNotInProof(U256),
+
+    /// Only transfers can be processed
+    TransactionUnsupported,
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e.to_string())
+    }
+}
@@ -0,0 +1,169 @@
+use crate::accounts::{address_from_pubkey, AddressedAccount};
+use crate::error::Error;
+use serde::Deserialize;
+use sheth::account::Account;
+use sheth::bls::PublicKey;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// The largest supported tree height, since addresses are 256 bits.
+pub const MAX_HEIGHT: usize = 256;
+
+/// A genesis file lists the accounts which exist in the initial state, e.g.:
+///
+/// ```json
+/// {
+///     "accounts": [
+///         { "pubkey": "0x0101...01", "balance": 1000, "nonce": 0 }
+///     ]
+/// }
+/// ```
+///
+/// Each account's address is derived from its public key, the same way as for random accounts.
+#[derive(Debug, Deserialize)]
+pub struct Genesis {
+    pub accounts: Vec<GenesisAccount>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenesisAccount {
+    /// Hex encoded 48 byte BLS public key, optionally prefixed by `0x`
+    pub pubkey: String,
+    pub balance: u64,
+    #[serde(default)]
+    pub nonce: u64,
+}
+
+/// Reads the genesis file at `path` and returns its accounts for a tree of height `height`.
+pub fn load<P: AsRef<Path>>(path: P, height: usize) -> Result<Vec<AddressedAccount>, Error> {
+    parse(&fs::read_to_string(path)?, height)
+}
+
+/// Parses a JSON encoded genesis and returns its accounts for a tree of height `height`.
+pub fn parse(json: &str, height: usize) -> Result<Vec<AddressedAccount>, Error> {
+    let genesis: Genesis =
+        serde_json::from_str(json).map_err(|e| Error::GenesisInvalid(e.to_string()))?;
+
+    genesis.accounts(height)
+}
+
+impl Genesis {
+    /// Returns the accounts at their addresses in a tree of height `height`. Fails if two public
+    /// keys map to the same address.
+    pub fn accounts(&self, height: usize) -> Result<Vec<AddressedAccount>, Error> {
+        if height == 0 || height > MAX_HEIGHT {
+            return Err(Error::HeightOutOfRange(height));
+        }
+
+        let mut seen = HashSet::new();
+
+        self.accounts
+            .iter()
+            .map(|account| {
+                let pubkey = parse_pubkey(&account.pubkey)?;
+                let address = address_from_pubkey(&pubkey, height);
+
+                if !seen.insert(address) {
+                    return Err(Error::AddressCollision(address));
+                }
+
+                Ok(AddressedAccount(
+                    address,
+                    Account {
+                        pubkey: PublicKey::new(pubkey),
+                        nonce: account.nonce,
+                        value: account.balance,
+                    },
+                ))
+            })
+            .collect()
+    }
+}
+
+fn parse_pubkey(s: &str) -> Result<[u8; 48], Error> {
+    let stripped = s.strip_prefix("0x").unwrap_or(s);
+
+    let bytes = hex::decode(stripped).map_err(|_| Error::PubkeyInvalid(s.to_string()))?;
+
+    if bytes.len() != 48 {
+        return Err(Error::PubkeyInvalid(s.to_string()));
+    }
+
+    let mut pubkey = [0u8; 48];
+    pubkey.copy_from_slice(&bytes);
+    Ok(pubkey)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn genesis(pubkeys: &[&str]) -> String {
+        let accounts: Vec<String> = pubkeys
+            .iter()
+            .enumerate()
+            .map(|(i, pubkey)| format!("{{ \"pubkey\": \"{}\", \"balance\": {} }}", pubkey, i))
+            .collect();
+
+        format!("{{ \"accounts\": [{}] }}", accounts.join(", "))
+    }
+
+    #[test]
+    fn parse_accounts() {
+        let one = "0x".to_string() + &"01".repeat(48);
+        let two = "02".repeat(48);
+
+        let accounts = parse(&genesis(&[&one, &two]), 256).unwrap();
+
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].0, address_from_pubkey(&[1u8; 48], 256));
+        assert_eq!(accounts[0].1.pubkey.as_bytes().to_vec(), vec![1u8; 48]);
+        assert_eq!(accounts[0].1.nonce, 0);
+        assert_eq!(accounts[1].0, address_from_pubkey(&[2u8; 48], 256));
+        assert_eq!(accounts[1].1.value, 1);
+    }
+
+    #[test]
+    fn reject_collisions() {
+        let one = "01".repeat(48);
+        let address = address_from_pubkey(&[1u8; 48], 256);
+
+        assert_eq!(
+            parse(&genesis(&[&one, &one]), 256).err(),
+            Some(Error::AddressCollision(address))
+        );
+
+        // In a tree with two leaves, three accounts must collide.
+        let pubkeys = ["01".repeat(48), "02".repeat(48), "03".repeat(48)];
+        let pubkeys: Vec<&str> = pubkeys.iter().map(|p| p.as_str()).collect();
+
+        match parse(&genesis(&pubkeys), 1) {
+            Err(Error::AddressCollision(_)) => (),
+            _ => panic!("expected collision"),
+        }
+    }
+
+    #[test]
+    fn reject_invalid_input() {
+        let one = "01".repeat(48);
+
+        assert_eq!(
+            parse(&genesis(&[&one]), 0).err(),
+            Some(Error::HeightOutOfRange(0))
+        );
+        assert_eq!(
+            parse(&genesis(&[&one]), 257).err(),
+            Some(Error::HeightOutOfRange(257))
+        );
+        assert_eq!(
+            parse(&genesis(&["0102"]), 256).err(),
+            Some(Error::PubkeyInvalid("0102".to_string()))
+        );
+
+        match parse("{ \"accounts\": 1 }", 256) {
+            Err(Error::GenesisInvalid(_)) => (),
+            _ => panic!("expected invalid genesis"),
+        }
+    }
+}
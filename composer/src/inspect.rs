@@ -0,0 +1,375 @@
+use crate::accounts::AddressedAccount;
+use crate::error::Error;
+use crate::proof::h256::H256;
+use crate::proof::offsets::indexes;
+use arrayref::array_ref;
+use bigint::{U256, U512};
+use sheth::account::Account;
+use sheth::bls::PublicKey;
+use sheth::deserialize_transactions;
+use sheth::transaction::Transaction;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// A decoded blob, where every 32 byte chunk of the proof is labeled with the node of the state
+/// tree that it belongs to.
+pub struct Inspection {
+    pub height: usize,
+    pub transactions: Vec<Transaction>,
+
+    /// The offsets table of the proof, starting with the number of chunks
+    pub offsets: Vec<u64>,
+
+    /// The chunks of the proof, in the order they appear in the blob
+    pub chunks: Vec<Chunk>,
+}
+
+/// A 32 byte value of the proof and its position in the state tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    /// General index of the node
+    pub index: U512,
+    pub value: H256,
+    pub node: Node,
+
+    /// Whether the chunk is the root of an empty subtree (e.g. an empty account)
+    pub zero: bool,
+}
+
+/// The node of the state tree which a chunk belongs to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Node {
+    /// An intermediate node above the accounts, at the given depth
+    Branch(usize),
+
+    /// The root of the account at the address
+    AccountRoot(U256),
+
+    /// A node within the account at the address
+    Account(U256, Field),
+
+    /// A node below the leaves of an account, which isn't part of the state
+    Unknown,
+}
+
+/// The nodes within an account's subtree:
+///
+/// ```text
+///             root
+///           /      \
+///          2        3
+///        /   \    /   \
+///       4  nonce value padding
+///      / \
+///    pk0 pk1
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    PubkeyNonce,
+    ValuePadding,
+    Pubkey,
+    Nonce,
+    Value,
+    Padding,
+
+    /// `pubkey[0..32]`
+    Pubkey0,
+
+    /// `pubkey[32..48]`, padded with zeros
+    Pubkey1,
+}
+
+impl Field {
+    /// Returns `true` if the field is a leaf of the account, rather than the root of some fields.
+    pub fn is_leaf(self) -> bool {
+        match self {
+            Field::Pubkey0 | Field::Pubkey1 | Field::Nonce | Field::Value | Field::Padding => true,
+            Field::PubkeyNonce | Field::ValuePadding | Field::Pubkey => false,
+        }
+    }
+}
+
+/// Decodes a blob (e.g. the output of `Blob::to_bytes`) from a tree of height `height`.
+pub fn inspect(blob: &[u8], height: usize) -> Result<Inspection, Error> {
+    let (transactions, proof) = decode_transactions(blob)?;
+    let (offsets, chunks) = decode_proof(proof, height)?;
+
+    Ok(Inspection {
+        height,
+        transactions,
+        offsets,
+        chunks,
+    })
+}
+
+/// Decodes a serialized proof (e.g. the output of `UncompressedProof::to_bytes`) into its offsets
+/// and its labeled chunks.
+pub fn decode_proof(proof: &[u8], height: usize) -> Result<(Vec<u64>, Vec<Chunk>), Error> {
+    if height == 0 || height > 256 {
+        return Err(Error::HeightOutOfRange(height));
+    }
+
+    if proof.len() < 8 {
+        return Err(Error::BlobInvalid("missing proof".to_string()));
+    }
+
+    let count = u64::from_le_bytes(*array_ref![proof, 0, 8]);
+    if count == 0 || count.saturating_mul(40) != proof.len() as u64 {
+        return Err(Error::BlobInvalid(format!(
+            "proof of {} bytes can't have {} chunks",
+            proof.len(),
+            count
+        )));
+    }
+
+    let count = count as usize;
+    let offsets: Vec<u64> = proof[..count * 8]
+        .chunks(8)
+        .map(|chunk| u64::from_le_bytes(*array_ref![chunk, 0, 8]))
+        .collect();
+
+    let indexes = indexes(&offsets)?;
+
+    let chunks = proof[count * 8..]
+        .chunks(32)
+        .zip(indexes)
+        .map(|(value, index)| {
+            let value = H256::new(array_ref![value, 0, 32]);
+            let node = node(index, height);
+            let zero = match node {
+                Node::Branch(depth) => value == zero_hash(height - depth),
+                Node::AccountRoot(_) => value == zero_hash(0),
+                _ => false,
+            };
+
+            Chunk {
+                index,
+                value,
+                node,
+                zero,
+            }
+        })
+        .collect();
+
+    Ok((offsets, chunks))
+}
+
+impl Inspection {
+    /// Returns the accounts whose leaves are all included in the proof.
+    pub fn accounts(&self) -> Vec<AddressedAccount> {
+        let mut addresses = BTreeSet::new();
+        let mut fields = HashMap::new();
+
+        for chunk in &self.chunks {
+            if let Node::Account(address, field) = chunk.node {
+                addresses.insert(address);
+                fields.insert((address, field), chunk.value.as_bytes());
+            }
+        }
+
+        addresses
+            .into_iter()
+            .filter_map(|address| {
+                let get = |field: Field| fields.get(&(address, field));
+
+                let mut pubkey = [0u8; 48];
+                pubkey[0..32].copy_from_slice(*get(Field::Pubkey0)?);
+                pubkey[32..48].copy_from_slice(&get(Field::Pubkey1)?[0..16]);
+
+                // Make sure the account is complete, even though the padding isn't used.
+                get(Field::Padding)?;
+
+                Some(AddressedAccount(
+                    address,
+                    Account {
+                        pubkey: PublicKey::new(pubkey),
+                        nonce: u64::from_le_bytes(*array_ref![get(Field::Nonce)?, 0, 8]),
+                        value: u64::from_le_bytes(*array_ref![get(Field::Value)?, 0, 8]),
+                    },
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Splits a blob into its transactions and its proof. The transactions are decoded by the EE, so
+/// the inspector reads them exactly as `sheth` does.
+fn decode_transactions(blob: &[u8]) -> Result<(Vec<Transaction>, &[u8]), Error> {
+    if blob.len() < 4 {
+        return Err(Error::BlobInvalid("missing transaction count".to_string()));
+    }
+
+    let count = u32::from_le_bytes(*array_ref![blob, 0, 4]) as usize;
+    let transactions = deserialize_transactions(blob, count)
+        .map_err(|_| Error::BlobInvalid(format!("too short for {} transactions", count)))?;
+
+    Ok((transactions, &blob[4 + count * 176..]))
+}
+
+/// Returns the node of a tree of height `height` at the general index `index`.
+pub(crate) fn node(index: U512, height: usize) -> Node {
+    let depth = index.bits() - 1;
+
+    if depth < height {
+        return Node::Branch(depth);
+    }
+
+    let root = index >> (depth - height);
+    let address = U256::from(root - (U512::one() << height));
+
+    let field = match (
+        depth - height,
+        (index - (root << (depth - height))).low_u32(),
+    ) {
+        (0, _) => return Node::AccountRoot(address),
+        (1, 0) => Field::PubkeyNonce,
+        (1, _) => Field::ValuePadding,
+        (2, 0) => Field::Pubkey,
+        (2, 1) => Field::Nonce,
+        (2, 2) => Field::Value,
+        (2, _) => Field::Padding,
+        (3, 0) => Field::Pubkey0,
+        (3, 1) => Field::Pubkey1,
+        _ => return Node::Unknown,
+    };
+
+    Node::Account(address, field)
+}
+
+fn zero_hash(depth: usize) -> H256 {
+    let mut buf = [0u8; 64];
+    sheth::hash::zh(depth, &mut buf);
+    H256::new(array_ref![buf, 0, 32])
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Transactions ({}):", self.transactions.len())?;
+
+        for (i, tx) in self.transactions.iter().enumerate() {
+            if let Transaction::Transfer(t) = tx {
+                writeln!(
+                    f,
+                    "  {}: from 0x{} to 0x{}, nonce {}, amount {}",
+                    i,
+                    hex::encode(<[u8; 32]>::from(t.from)),
+                    hex::encode(<[u8; 32]>::from(t.to)),
+                    t.nonce,
+                    t.amount
+                )?;
+            }
+        }
+
+        writeln!(f, "\nOffsets ({}):", self.offsets.len())?;
+        writeln!(f, "  {:?}", self.offsets)?;
+
+        writeln!(f, "\nChunks ({}):", self.chunks.len())?;
+
+        for chunk in &self.chunks {
+            write!(
+                f,
+                "  {:>6} 0x{}  ",
+                chunk.index.to_string(),
+                hex::encode(chunk.value.as_bytes())
+            )?;
+
+            write!(f, "{}", chunk.node)?;
+
+            if chunk.zero {
+                write!(f, " (empty)")?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Node::Branch(depth) => write!(f, "branch at depth {}", depth),
+            Node::AccountRoot(address) => write!(f, "account 0x{} root", address_hex(address)),
+            Node::Account(address, field) => {
+                write!(f, "account 0x{} {:?}", address_hex(address), field)
+            }
+            Node::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+fn address_hex(address: U256) -> String {
+    let mut buf = [0u8; 32];
+    address.to_big_endian(&mut buf);
+    hex::encode(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blob;
+
+    #[test]
+    fn inspect_generated_blob() {
+        let blob = blob::generate(3, 2, 8).unwrap();
+        let inspection = inspect(&blob.to_bytes(), 8).unwrap();
+
+        assert_eq!(inspection.transactions.len(), 2);
+        assert_eq!(
+            crate::transactions::serialize(&inspection.transactions),
+            crate::transactions::serialize(&blob.transactions)
+        );
+
+        // Every account contributes its five leaves.
+        let leaves = inspection
+            .chunks
+            .iter()
+            .filter(|c| matches!(c.node, Node::Account(_, _)))
+            .count();
+        assert_eq!(leaves, 15);
+
+        let mut expected: Vec<U256> = blob.accounts.iter().map(|a| a.0).collect();
+        expected.sort();
+        let found: Vec<U256> = inspection.accounts().iter().map(|a| a.0).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn label_nodes() {
+        // The only account is at address 1, so indexes = [2, 24, 25, 13, 14, 15]
+        let blob = blob::generate(1, 0, 1).unwrap();
+        assert_eq!(blob.accounts[0].0, 1.into());
+
+        let inspection = inspect(&blob.to_bytes(), 1).unwrap();
+        let nodes: Vec<Node> = inspection.chunks.iter().map(|c| c.node).collect();
+
+        assert_eq!(
+            nodes,
+            vec![
+                Node::AccountRoot(0.into()),
+                Node::Account(1.into(), Field::Pubkey0),
+                Node::Account(1.into(), Field::Pubkey1),
+                Node::Account(1.into(), Field::Nonce),
+                Node::Account(1.into(), Field::Value),
+                Node::Account(1.into(), Field::Padding),
+            ]
+        );
+        assert!(inspection.chunks[0].zero);
+        assert!(!inspection.chunks[1].zero);
+    }
+
+    #[test]
+    fn reject_malformed_blobs() {
+        let bytes = blob::generate(2, 1, 8).unwrap().to_bytes();
+
+        assert!(inspect(&bytes[..3], 8).is_err());
+        assert!(inspect(&bytes[..100], 8).is_err());
+        assert!(inspect(&bytes[..bytes.len() - 1], 8).is_err());
+
+        // The first offset must be smaller than the number of chunks
+        let mut bytes = bytes.clone();
+        bytes[4 + 176 + 8] = 0xff;
+        assert!(inspect(&bytes, 8).is_err());
+    }
+}
@@ -1,4 +1,13 @@
 pub mod accounts;
+pub mod analyze;
 pub mod blob;
+pub mod db;
+pub mod diff;
+pub mod error;
+pub mod genesis;
+pub mod inspect;
 pub mod proof;
+pub mod render;
+pub mod smt;
 pub mod transactions;
+pub mod workload;
@@ -1,3 +1,4 @@
+use crate::error::Error;
 use bigint::U512;
 
 /// Returns a vector of offsets that is read by an in-place lookup algorithm to determine the
@@ -5,75 +6,144 @@ use bigint::U512;
 ///
 /// For more info, see: https://github.com/protolambda/eth-merkle-trees
 pub fn calculate(indexes: Vec<U512>) -> Vec<u64> {
-    let mut raw_indexes = vec![];
+    let width = indexes.iter().map(|index| index.bits()).max().unwrap_or(1);
 
-    // Convert indexes into arrays of bits
-    for index in indexes.clone() {
-        let mut bits = vec![0u8; 260];
-        for i in 0..260 {
-            bits[260 - i - 1] = index.bit(i) as u8;
-        }
-
-        raw_indexes.push(bits);
-    }
-
-    // Translate everything to an end node (padding with 1s from the right)
-    let raw_indexes = raw_indexes
+    // Translate everything to an end node (padding with 1s from the right), so that the indexes
+    // all have the same depth and sort from left to right.
+    let mut end_nodes: Vec<U512> = indexes
         .iter()
         .map(|index| {
-            let mut index = index.clone();
-
-            while index[0] == 0 {
-                index.remove(0);
-                index.push(1);
-            }
-
-            index
+            let shift = width - index.bits();
+            (*index << shift) + ((U512::one() << shift) - U512::one())
         })
         .collect();
 
+    end_nodes.sort();
+
     let mut ret: Vec<u64> = vec![indexes.len() as u64];
-    ret.extend(helper(raw_indexes));
+
+    // The most significant bit is the root, so the first branch is decided by the next bit
+    if width >= 2 {
+        helper(&end_nodes, width - 2, &mut ret);
+    }
+
     ret
 }
 
-fn helper(indexes: Vec<Vec<u8>>) -> Vec<u64> {
-    if indexes.len() <= 1 || indexes[0].len() == 0 {
-        return vec![];
+/// Appends the offsets of the subtree which contains the sorted `indexes`, where `bit` decides
+/// whether an index belongs to the left or the right subtree.
+fn helper(indexes: &[U512], bit: usize, ret: &mut Vec<u64>) {
+    if indexes.len() <= 1 {
+        return;
     }
 
-    let mut left_subtree: Vec<Vec<u8>> = vec![];
-    let mut right_subtree: Vec<Vec<u8>> = vec![];
+    let split = indexes.partition_point(|index| !index.bit(bit));
 
-    for mut index in indexes {
-        let bit = index.remove(0);
+    if split != 0 {
+        ret.push(split as u64);
+    }
 
-        if bit == 0 {
-            left_subtree.push(index);
-        } else {
-            right_subtree.push(index);
-        }
+    if bit > 0 {
+        helper(&indexes[..split], bit - 1, ret);
+        helper(&indexes[split..], bit - 1, ret);
     }
+}
 
-    let left_subtree_size = left_subtree.len() as u64;
-    let left_subtree_offsets = helper(left_subtree);
-    let right_subtree_offsets = helper(right_subtree);
+/// Returns the general indexes of the multiproof described by `offsets`, in the order of their
+/// values in the proof. This is the inverse of `calculate`, so `offsets` starts with the number of
+/// values.
+pub fn indexes(offsets: &[u64]) -> Result<Vec<U512>, Error> {
+    fn helper(offsets: &[u64], size: u64, index: U512, ret: &mut Vec<U512>) -> Result<(), Error> {
+        if size == 1 {
+            ret.push(index);
+            return Ok(());
+        }
 
-    let mut ret = if left_subtree_size == 0 {
-        vec![]
-    } else {
-        vec![left_subtree_size]
-    };
+        if index.bits() >= 512 {
+            return Err(Error::OffsetsInvalid("tree is too deep".to_string()));
+        }
 
-    ret.extend(left_subtree_offsets);
-    ret.extend(right_subtree_offsets);
+        // A subtree of `size` values is always described by `size - 1` offsets.
+        let left = offsets[0];
+        if left == 0 || left >= size {
+            return Err(Error::OffsetsInvalid(format!(
+                "offset {} in a subtree of {} values",
+                left, size
+            )));
+        }
 
-    ret
+        helper(&offsets[1..], left, index << 1, ret)?;
+        helper(
+            &offsets[left as usize..],
+            size - left,
+            (index << 1) + 1.into(),
+            ret,
+        )
+    }
+
+    match offsets.first() {
+        Some(&count) if count > 0 && count == offsets.len() as u64 => {
+            let mut ret = vec![];
+            helper(&offsets[1..], count, U512::one(), &mut ret)?;
+            Ok(ret)
+        }
+        _ => Err(Error::OffsetsInvalid(
+            "number of values doesn't match the number of offsets".to_string(),
+        )),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+    /// Returns the leaves of a random tree, where every node has either zero or two children. The
+    /// root always has children, since a single value doesn't need any offsets.
+    fn random_tree<R: Rng>(rng: &mut R, index: U512, max_depth: usize) -> Vec<U512> {
+        if index.bits() > max_depth || (index > U512::one() && rng.gen_bool(0.45)) {
+            return vec![index];
+        }
+
+        let mut ret = random_tree(rng, index << 1, max_depth);
+        ret.extend(random_tree(rng, (index << 1) + 1.into(), max_depth));
+        ret
+    }
+
+    #[test]
+    fn indexes_round_trip() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..500 {
+            let max_depth = rng.gen_range(2, 48);
+            let indexes = random_tree(&mut rng, U512::one(), max_depth);
+
+            let mut shuffled = indexes.clone();
+            shuffled.shuffle(&mut rng);
+
+            assert_eq!(super::indexes(&calculate(shuffled)), Ok(indexes));
+        }
+    }
+
+    #[test]
+    fn indexes_reject_invalid_offsets() {
+        assert!(indexes(&[]).is_err());
+        assert!(indexes(&[2]).is_err());
+        assert!(indexes(&[3, 3, 1]).is_err());
+        assert!(indexes(&[3, 0, 1]).is_err());
+        assert_eq!(indexes(&[1]), Ok(vec![U512::one()]));
+        assert_eq!(
+            indexes(&[6, 5, 3, 2, 1, 1]),
+            Ok(vec![
+                16.into(),
+                17.into(),
+                9.into(),
+                10.into(),
+                11.into(),
+                3.into()
+            ])
+        );
+    }
 
     #[test]
     fn offset_4_bit_left() {
@@ -3,17 +3,20 @@ use std::ops::Shl;
 
 /// Sort a vector bit-alphabetically
 ///
+/// Indexes are ordered as they are visited by an in-order traversal of the tree, so every node
+/// comes after its left subtree and before its right subtree. This holds for any set of indexes,
+/// including ancestors mixed with their descendants.
+///
 /// For more info, see: https://github.com/ethereum/eth2.0-specs/issues/1303
 pub fn alpha_sort(n: &Vec<U512>) -> Vec<U512> {
     let mut ret = n.clone();
 
+    // Appending a `1` bit places a node between its left subtree (which continues with a `0`) and
+    // its right subtree (which continues with a `1` and has more bits). Once right padded to the
+    // same length, the indexes can be compared as numbers.
     ret.sort_by(|a, b| {
-        let (a, a_shift, b, b_shift) = normalize(*a, *b);
-        match a.cmp(&b) {
-            std::cmp::Ordering::Less => std::cmp::Ordering::Less,
-            std::cmp::Ordering::Greater => std::cmp::Ordering::Greater,
-            std::cmp::Ordering::Equal => a_shift.cmp(&b_shift),
-        }
+        let (a, _, b, _) = normalize((*a << 1) + 1.into(), (*b << 1) + 1.into());
+        a.cmp(&b)
     });
 
     ret
@@ -81,10 +84,9 @@ mod test {
         assert_eq!(alpha_sort(&unsorted), sorted);
     }
 
-    #[ignore] // Current implementation only works on branches
     #[test]
     fn alpha_sort_many_numbers() {
-        let unsorted: Vec<U512> = (1..4).fold(vec![], |mut acc, n| {
+        let unsorted: Vec<U512> = (1..32).fold(vec![], |mut acc, n| {
             acc.push(n.into());
             acc
         });
@@ -101,4 +103,20 @@ mod test {
 
         assert_eq!(alpha_sort(&unsorted), sorted);
     }
+
+    #[test]
+    fn alpha_sort_mixed_depths() {
+        // 8 is the parent of 16 and 17, 2 is an ancestor of 19 and 5, and 3 is on the right.
+        let unsorted: Vec<U512> = vec![3, 5, 2, 17, 8, 19, 16, 96]
+            .into_iter()
+            .map(U512::from)
+            .collect();
+
+        let sorted: Vec<U512> = vec![16, 8, 17, 19, 2, 5, 96, 3]
+            .into_iter()
+            .map(U512::from)
+            .collect();
+
+        assert_eq!(alpha_sort(&unsorted), sorted);
+    }
 }
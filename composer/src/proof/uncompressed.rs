@@ -1,6 +1,8 @@
 use crate::accounts::AddressedAccount;
 use crate::proof::h256::H256;
+use crate::proof::offsets::calculate as calculate_offsets;
 use crate::proof::sort::alpha_sort;
+use crate::smt::hash_pairs;
 use arrayref::array_ref;
 use bigint::U512;
 use sheth::hash::{hash, zh};
@@ -12,6 +14,24 @@ pub struct UncompressedProof {
     pub values: Vec<H256>,
 }
 
+impl UncompressedProof {
+    /// Returns the proof in the compressed format that is read by `imp` (e.g. the offsets followed
+    /// by the values).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let offsets = calculate_offsets(self.indexes.clone());
+
+        let ret = offsets.iter().fold(vec![], |mut acc, x| {
+            acc.extend(&x.to_le_bytes());
+            acc
+        });
+
+        self.values.iter().fold(ret, |mut acc, x| {
+            acc.extend(x.as_bytes());
+            acc
+        })
+    }
+}
+
 pub fn generate(accounts: Vec<AddressedAccount>, height: usize) -> UncompressedProof {
     let mut map = init_multiproof(accounts, height);
     let indexes = fill_proof(&mut map, height);
@@ -52,73 +72,95 @@ pub fn init_multiproof(accounts: Vec<AddressedAccount>, height: usize) -> HashMa
     map
 }
 
+/// Calculates every node between the leaves in `map` and the root, one level at a time from the
+/// bottom of the tree. Returns the indexes of the proof, which are the leaves along with the roots
+/// of any empty subtrees they require.
 fn fill_proof(map: &mut HashMap<U512, H256>, height: usize) -> Vec<U512> {
-    let mut indexes: Vec<U512> = map.keys().map(|x| x.to_owned()).collect();
-    indexes.sort();
-    indexes.reverse();
-
-    let mut proof_indexes: Vec<U512> = indexes.clone();
-
-    let mut position = 0;
-
-    while indexes[position] > U512::from(1) {
-        let left = indexes[position] & (!U512::zero() - U512::one());
-        let right = left + 1.into();
-        let parent = left / 2.into();
-
-        if !map.contains_key(&parent) {
-            let left = get_or_generate(
-                map,
-                &mut proof_indexes,
-                height,
-                left,
-                indexes[position].bits(),
-            );
-
-            let right = get_or_generate(
-                map,
-                &mut proof_indexes,
-                height,
-                right,
-                indexes[position].bits(),
-            );
-
-            // Calculate hash
-            let mut buf = [0u8; 64];
-            buf[0..32].copy_from_slice(left.as_bytes());
-            buf[32..64].copy_from_slice(right.as_bytes());
-            hash(&mut buf);
-
-            // Insert hash to map
-            map.insert(parent, H256::new(array_ref![buf, 0, 32]));
-
-            // Push parent index to calculate next level
-            indexes.push(parent);
+    let mut proof_indexes: Vec<U512> = map.keys().cloned().collect();
+    let zeros = zero_hashes(height);
+
+    // Group the nodes by depth, where `levels[i]` holds the nodes with `i` bits
+    let mut levels: Vec<Vec<U512>> = vec![];
+    for index in map.keys() {
+        let bits = index.bits();
+        if levels.len() <= bits {
+            levels.resize(bits + 1, vec![]);
         }
+        levels[bits].push(*index);
+    }
+
+    for bits in (2..levels.len()).rev() {
+        let mut level = std::mem::take(&mut levels[bits]);
+        level.sort();
+
+        let mut previous: Option<U512> = None;
+        let mut parents = vec![];
+        let mut pairs = vec![];
+
+        for index in level {
+            let left = index & !U512::one();
+
+            // Both children of a parent are processed together
+            if previous == Some(left) {
+                continue;
+            }
+            previous = Some(left);
+
+            let right = left + 1.into();
+            let parent = left >> 1;
 
-        indexes.sort();
-        indexes.reverse();
-        position += 1;
+            if map.contains_key(&parent) {
+                continue;
+            }
+
+            let left = get_or_generate(map, &mut proof_indexes, &zeros, left, bits);
+            let right = get_or_generate(map, &mut proof_indexes, &zeros, right, bits);
+
+            parents.push(parent);
+            pairs.push((left, right));
+        }
+
+        // Insert the hashes to map and queue the parents for the next level
+        for (parent, node) in parents.into_iter().zip(hash_pairs(&pairs)) {
+            map.insert(parent, node);
+            levels[bits - 1].push(parent);
+        }
     }
 
     proof_indexes
 }
 
+/// Returns the roots of empty subtrees, where `zeros[i]` is the root of a subtree of depth `i`
+/// above the accounts (see `sheth::hash::zh`).
+fn zero_hashes(height: usize) -> Vec<H256> {
+    let mut buf = [0u8; 64];
+    zh(0, &mut buf);
+
+    let mut zeros = vec![H256::new(array_ref![buf, 0, 32])];
+
+    for depth in 0..height {
+        buf[0..32].copy_from_slice(zeros[depth].as_bytes());
+        buf[32..64].copy_from_slice(zeros[depth].as_bytes());
+        hash(&mut buf);
+        zeros.push(H256::new(array_ref![buf, 0, 32]));
+    }
+
+    zeros
+}
+
 fn get_or_generate(
     map: &mut HashMap<U512, H256>,
     proof_indexes: &mut Vec<U512>,
-    height: usize,
+    zeros: &[H256],
     index: U512,
     zero_bits: usize,
 ) -> H256 {
     match map.get(&index) {
-        Some(x) => x.clone(),
+        Some(x) => *x,
         None => {
-            let mut buf = [0u8; 64];
-            zh(height + 1 - zero_bits, &mut buf);
-            let buf = H256::new(array_ref![buf, 0, 32]);
+            let buf = zeros[zeros.len() - zero_bits];
             proof_indexes.push(index);
-            map.insert(index, buf.clone());
+            map.insert(index, buf);
             buf
         }
     }
@@ -0,0 +1,259 @@
+use crate::error::Error;
+use crate::inspect::{decode_proof, node, Chunk, Node};
+use crate::proof::uncompressed::UncompressedProof;
+use bigint::U512;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// The output format of a rendered proof.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// A Graphviz digraph, e.g. `dot -Tsvg proof.dot > proof.svg`
+    Dot,
+
+    /// An indented tree, which is only practical for small trees
+    Ascii,
+}
+
+/// How a node of a rendered proof is obtained.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// The node is a chunk of the proof
+    Provided,
+
+    /// The node is calculated from its children
+    Computed,
+
+    /// The node is a chunk of the proof which is the root of an empty subtree
+    Zero,
+
+    /// The node is a chunk of the proof which holds a field of an account
+    Leaf,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Provided => "provided",
+            Kind::Computed => "computed",
+            Kind::Zero => "zero",
+            Kind::Leaf => "leaf",
+        }
+    }
+}
+
+/// Renders a serialized proof (e.g. the output of `UncompressedProof::to_bytes`) of a tree of
+/// height `height`.
+pub fn render(proof: &[u8], height: usize, format: Format) -> Result<String, Error> {
+    let (_, chunks) = decode_proof(proof, height)?;
+    let tree = Tree::new(chunks, height);
+
+    Ok(match format {
+        Format::Dot => tree.dot(),
+        Format::Ascii => tree.ascii(),
+    })
+}
+
+/// Renders an uncompressed proof of a tree of height `height`.
+pub fn render_uncompressed(
+    proof: &UncompressedProof,
+    height: usize,
+    format: Format,
+) -> Result<String, Error> {
+    render(&proof.to_bytes(), height, format)
+}
+
+/// The chunks of a proof along with every node that is calculated from them.
+struct Tree {
+    height: usize,
+    chunks: HashMap<U512, Chunk>,
+    nodes: BTreeSet<U512>,
+}
+
+impl Tree {
+    fn new(chunks: Vec<Chunk>, height: usize) -> Self {
+        let mut nodes = BTreeSet::new();
+
+        for chunk in &chunks {
+            let mut index = chunk.index;
+            while !index.is_zero() && nodes.insert(index) {
+                index = index >> 1;
+            }
+        }
+
+        Tree {
+            height,
+            chunks: chunks.into_iter().map(|c| (c.index, c)).collect(),
+            nodes,
+        }
+    }
+
+    fn kind(&self, index: &U512) -> Kind {
+        match self.chunks.get(index) {
+            None => Kind::Computed,
+            Some(chunk) if chunk.zero => Kind::Zero,
+            Some(chunk) => match chunk.node {
+                Node::Account(_, field) if field.is_leaf() => Kind::Leaf,
+                _ => Kind::Provided,
+            },
+        }
+    }
+
+    fn label(&self, index: &U512) -> String {
+        format!("{} {}", index, node(*index, self.height))
+    }
+
+    fn children(&self, index: &U512) -> Vec<U512> {
+        let left = *index << 1;
+        let right = left + U512::one();
+
+        [left, right]
+            .iter()
+            .filter(|child| self.nodes.contains(child))
+            .cloned()
+            .collect()
+    }
+
+    fn dot(&self) -> String {
+        let mut out = String::from("digraph proof {\n");
+        out.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+
+        for index in &self.nodes {
+            let style = match self.kind(index) {
+                Kind::Provided => "solid",
+                Kind::Computed => "dashed",
+                Kind::Zero => "filled, fillcolor=lightgrey",
+                Kind::Leaf => "filled, fillcolor=lightblue",
+            };
+
+            let value = match self.chunks.get(index) {
+                Some(chunk) => format!("\\n0x{}", &hex::encode(chunk.value.as_bytes())[0..8]),
+                None => String::new(),
+            };
+
+            writeln!(
+                out,
+                "  n{} [label=\"{}\\n{}{}\", style={}];",
+                index,
+                self.label(index),
+                self.kind(index).name(),
+                value,
+                style
+            )
+            .unwrap();
+        }
+
+        for index in &self.nodes {
+            for child in self.children(index) {
+                writeln!(out, "  n{} -> n{};", index, child).unwrap();
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    fn ascii(&self) -> String {
+        let mut out = String::new();
+
+        if let Some(root) = self.nodes.iter().next() {
+            self.ascii_node(root, "", "", &mut out);
+        }
+
+        out
+    }
+
+    fn ascii_node(&self, index: &U512, first: &str, rest: &str, out: &mut String) {
+        writeln!(
+            out,
+            "{}{} [{}]",
+            first,
+            self.label(index),
+            self.kind(index).name()
+        )
+        .unwrap();
+
+        let children = self.children(index);
+
+        for (i, child) in children.iter().enumerate() {
+            if i + 1 == children.len() {
+                self.ascii_node(child, &format!("{}└─ ", rest), &format!("{}   ", rest), out);
+            } else {
+                self.ascii_node(child, &format!("{}├─ ", rest), &format!("{}│  ", rest), out);
+            }
+        }
+    }
+}
+
+/// Parses `dot` or `ascii`.
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "ascii" => Ok(Format::Ascii),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blob;
+    use crate::proof::uncompressed::generate;
+
+    #[test]
+    fn render_ascii() {
+        // The only account is at address 1, so indexes = [2, 24, 25, 13, 14, 15]
+        let blob = blob::generate(1, 0, 1).unwrap();
+        let output = render(&blob.proof, 1, Format::Ascii).unwrap();
+
+        let lines: Vec<String> = output
+            .lines()
+            .map(|line| {
+                let index_end = line.find(|c: char| c.is_ascii_digit()).unwrap();
+                let index = line[index_end..].split(' ').next().unwrap();
+                let kind = line.rsplit(' ').next().unwrap();
+                format!("{}{} {}", &line[..index_end], index, kind)
+            })
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "1 [computed]",
+                "├─ 2 [zero]",
+                "└─ 3 [computed]",
+                "   ├─ 6 [computed]",
+                "   │  ├─ 12 [computed]",
+                "   │  │  ├─ 24 [leaf]",
+                "   │  │  └─ 25 [leaf]",
+                "   │  └─ 13 [leaf]",
+                "   └─ 7 [computed]",
+                "      ├─ 14 [leaf]",
+                "      └─ 15 [leaf]",
+            ]
+        );
+        assert!(output.contains("3 account 0x"));
+    }
+
+    #[test]
+    fn render_dot() {
+        let accounts = crate::accounts::random_accounts(3, 8).unwrap();
+        let proof = generate(accounts, 8);
+        let output = render_uncompressed(&proof, 8, Format::Dot).unwrap();
+
+        assert!(output.starts_with("digraph proof {"));
+        assert!(output.contains("n1 -> n2;"));
+        assert!(output.contains("n1 -> n3;"));
+
+        // Every node except the root has exactly one parent.
+        let nodes = output.matches("[label=").count();
+        let edges = output.matches(" -> ").count();
+        assert_eq!(edges, nodes - 1);
+        assert_eq!(output.matches("lightblue").count(), 15);
+    }
+}
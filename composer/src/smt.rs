@@ -0,0 +1,628 @@
+use crate::accounts::AddressedAccount;
+use crate::error::Error;
+use crate::proof::h256::H256;
+use crate::proof::sort::alpha_sort;
+use crate::proof::uncompressed::{init_multiproof, UncompressedProof};
+use arrayref::array_ref;
+use bigint::{U256, U512};
+use rayon::prelude::*;
+use sheth::account::Account;
+use sheth::address::Address;
+use sheth::hash::{hash, zh};
+use sheth::state::State;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// A sparse merkle tree which holds every account in the state.
+///
+/// Only the account roots and the intermediate nodes above them are stored. A node that is missing
+/// from `nodes` is the root of an empty subtree, so its value is the zero hash for its depth (see
+/// `sheth::hash::zh`).
+#[derive(Clone)]
+pub struct SparseMerkleTree {
+    height: usize,
+    accounts: BTreeMap<U256, Account>,
+    nodes: HashMap<U512, H256>,
+    zeros: Vec<H256>,
+}
+
+impl SparseMerkleTree {
+    /// Returns an empty tree where `height` is the number of levels above the account roots.
+    pub fn new(height: usize) -> Self {
+        let mut buf = [0u8; 64];
+        zh(0, &mut buf);
+
+        let mut zeros = vec![H256::new(array_ref![buf, 0, 32])];
+        for depth in 0..height {
+            zeros.push(hash_pair(&zeros[depth], &zeros[depth]));
+        }
+
+        SparseMerkleTree {
+            height,
+            accounts: BTreeMap::new(),
+            nodes: HashMap::new(),
+            zeros,
+        }
+    }
+
+    /// Returns a tree that contains each of `accounts`.
+    pub fn from_accounts(accounts: Vec<AddressedAccount>, height: usize) -> Result<Self, Error> {
+        let mut tree = Self::new(height);
+
+        for AddressedAccount(address, account) in accounts {
+            tree.check_range(address)?;
+
+            if tree.accounts.insert(address, account).is_some() {
+                return Err(Error::AccountExists(address));
+            }
+        }
+
+        tree.rebuild();
+        Ok(tree)
+    }
+
+    /// Returns a tree from previously calculated nodes, without rehashing any accounts.
+    pub(crate) fn from_parts(
+        height: usize,
+        accounts: BTreeMap<U256, Account>,
+        nodes: HashMap<U512, H256>,
+    ) -> Self {
+        let mut tree = Self::new(height);
+        tree.accounts = accounts;
+        tree.nodes = nodes;
+        tree
+    }
+
+    pub(crate) fn nodes(&self) -> &HashMap<U512, H256> {
+        &self.nodes
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Returns the account at `address`, if one exists.
+    pub fn get(&self, address: &U256) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    /// Returns every account in the tree, ordered by address.
+    pub fn accounts(&self) -> Vec<AddressedAccount> {
+        self.accounts
+            .iter()
+            .map(|(address, account)| AddressedAccount(*address, account.clone()))
+            .collect()
+    }
+
+    /// Adds a new account at `address`.
+    pub fn insert(&mut self, address: U256, account: Account) -> Result<(), Error> {
+        self.check_range(address)?;
+
+        if self.accounts.contains_key(&address) {
+            return Err(Error::AccountExists(address));
+        }
+
+        self.set(address, Some(account));
+        Ok(())
+    }
+
+    /// Replaces the account at `address` and returns the previous account.
+    pub fn update(&mut self, address: U256, account: Account) -> Result<Account, Error> {
+        let previous = self
+            .accounts
+            .get(&address)
+            .cloned()
+            .ok_or(Error::AccountUnknown(address))?;
+
+        self.set(address, Some(account));
+        Ok(previous)
+    }
+
+    /// Removes the account at `address` and returns it.
+    pub fn delete(&mut self, address: U256) -> Result<Account, Error> {
+        let previous = self
+            .accounts
+            .get(&address)
+            .cloned()
+            .ok_or(Error::AccountUnknown(address))?;
+
+        self.set(address, None);
+        Ok(previous)
+    }
+
+    /// Returns the root of the state.
+    pub fn root(&self) -> [u8; 32] {
+        *self.node(&U512::one()).as_bytes()
+    }
+
+    /// Returns a multiproof for the accounts at `addresses` that can be loaded by `imp`. Addresses
+    /// without an account are proven as zero accounts, so they can still receive transfers.
+    pub fn proof(&self, addresses: &[U256]) -> Result<UncompressedProof, Error> {
+        let mut accounts = vec![];
+        let mut paths: HashSet<U512> = HashSet::new();
+
+        for address in addresses {
+            self.check_range(*address)?;
+
+            let account = self
+                .accounts
+                .get(address)
+                .cloned()
+                .unwrap_or_else(Account::zero);
+            accounts.push(AddressedAccount(*address, account));
+
+            let mut index = self.leaf_index(*address);
+            while index > U512::zero() && paths.insert(index) {
+                index = index >> 1;
+            }
+        }
+
+        let mut map = init_multiproof(accounts, self.height);
+
+        // Every node which isn't on a path to a proven account, but whose sibling is, must be
+        // provided so that the verifier can compute the root.
+        for index in paths.iter() {
+            let sibling = *index ^ U512::one();
+
+            if *index > U512::one() && !paths.contains(&sibling) {
+                map.insert(sibling, self.node(&sibling));
+            }
+        }
+
+        let indexes = alpha_sort(&map.keys().cloned().collect());
+        let values = indexes.iter().map(|i| map[i]).collect();
+
+        Ok(UncompressedProof { indexes, values })
+    }
+
+    /// Returns a multiproof for an arbitrary set of nodes, e.g. only some fields of an account or
+    /// whole subtrees. A node which is an ancestor of another requested node is proven by its
+    /// descendants, so it isn't included itself.
+    pub fn multiproof(&self, indexes: &[U512]) -> Result<UncompressedProof, Error> {
+        let mut paths: HashSet<U512> = HashSet::new();
+
+        for index in indexes {
+            self.value_at(index)?;
+
+            let mut parent = *index >> 1;
+            while parent > U512::zero() && paths.insert(parent) {
+                parent = parent >> 1;
+            }
+        }
+
+        let mut map: HashMap<U512, H256> = HashMap::new();
+
+        for index in indexes {
+            if !paths.contains(index) {
+                map.insert(*index, self.value_at(index)?);
+            }
+        }
+
+        // Every child of a node on a path must either be on a path itself or be provided.
+        for index in paths.iter() {
+            for child in [*index << 1, (*index << 1) + U512::one()].iter() {
+                if !paths.contains(child) && !map.contains_key(child) {
+                    map.insert(*child, self.value_at(child)?);
+                }
+            }
+        }
+
+        let indexes = alpha_sort(&map.keys().cloned().collect());
+        let values = indexes.iter().map(|i| map[i]).collect();
+
+        Ok(UncompressedProof { indexes, values })
+    }
+
+    /// Returns the value of any node in the tree, including the nodes within an account.
+    pub(crate) fn value_at(&self, index: &U512) -> Result<H256, Error> {
+        if index.is_zero() {
+            return Err(Error::IndexInvalid(*index));
+        }
+
+        let depth = index.bits() - 1;
+        if depth <= self.height {
+            return Ok(self.node(index));
+        }
+
+        // The index is within an account's subtree, so find its position relative to the account
+        // root (e.g. `5` for the nonce).
+        let below = depth - self.height;
+        if below > 3 {
+            return Err(Error::IndexInvalid(*index));
+        }
+
+        let root = *index >> below;
+        let relative = (1u64 << below) + (*index - (root << below)).low_u64();
+        let address = U256::from(root - (U512::one() << self.height));
+
+        let account = self
+            .accounts
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(Account::zero);
+
+        account_node(&account, relative).ok_or(Error::IndexInvalid(*index))
+    }
+
+    /// Returns whether the cached account roots match the accounts. Every account has a leaf node,
+    /// and there are no leaf nodes without an account.
+    pub(crate) fn leaves_match(&self) -> bool {
+        let leaves = self
+            .nodes
+            .keys()
+            .filter(|index| index.bits() == self.height + 1)
+            .count();
+
+        leaves == self.accounts.len()
+            && self.accounts.par_iter().all(|(address, account)| {
+                self.check_range(*address).is_ok()
+                    && self.nodes.get(&self.leaf_index(*address)) == Some(&account_root(account))
+            })
+    }
+
+    fn check_range(&self, address: U256) -> Result<(), Error> {
+        if self.height < 256 && address >= U256::one() << self.height {
+            Err(Error::AddressOutOfRange(address))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn leaf_index(&self, address: U256) -> U512 {
+        (U512::one() << self.height) + U512::from(address)
+    }
+
+    fn node(&self, index: &U512) -> H256 {
+        match self.nodes.get(index) {
+            Some(node) => *node,
+            None => self.zeros[self.height + 1 - index.bits()],
+        }
+    }
+
+    /// Recalculates every node from the accounts, one level at a time from the bottom of the tree.
+    /// This results in the same nodes as inserting the accounts one by one, but the nodes of each
+    /// level are hashed in parallel.
+    fn rebuild(&mut self) {
+        let leaves: Vec<(U512, H256)> = self
+            .accounts
+            .par_iter()
+            .map(|(address, account)| (self.leaf_index(*address), account_root(account)))
+            .collect();
+
+        // The accounts are sorted by address, so each level is sorted as well
+        let mut level: Vec<U512> = leaves.iter().map(|(index, _)| *index).collect();
+        self.nodes = leaves.into_iter().collect();
+
+        for _ in 0..self.height {
+            let mut parents: Vec<U512> = level.iter().map(|index| *index >> 1).collect();
+            parents.dedup();
+
+            let nodes: Vec<H256> = parents
+                .par_iter()
+                .map(|parent| {
+                    let left = *parent << 1;
+                    hash_pair(&self.node(&left), &self.node(&(left + U512::one())))
+                })
+                .collect();
+
+            self.nodes.extend(parents.iter().cloned().zip(nodes));
+            level = parents;
+        }
+    }
+
+    /// Sets the account at `address` and recalculates the nodes along its path to the root.
+    fn set(&mut self, address: U256, account: Option<Account>) {
+        let mut index = self.leaf_index(address);
+
+        match account {
+            Some(account) => {
+                self.nodes.insert(index, account_root(&account));
+                self.accounts.insert(address, account);
+            }
+            None => {
+                self.nodes.remove(&index);
+                self.accounts.remove(&address);
+            }
+        }
+
+        while index > U512::one() {
+            let left = index & !U512::one();
+            let right = left + U512::one();
+            let parent = index >> 1;
+
+            if self.nodes.contains_key(&left) || self.nodes.contains_key(&right) {
+                let node = hash_pair(&self.node(&left), &self.node(&right));
+                self.nodes.insert(parent, node);
+            } else {
+                self.nodes.remove(&parent);
+            }
+
+            index = parent;
+        }
+    }
+
+    fn account_or_zero(&self, address: Address) -> Result<(U256, Account), sheth::error::Error> {
+        let address = U256::from(<[u8; 32]>::from(address));
+
+        self.check_range(address)
+            .map_err(|_| sheth::error::Error::StateIncomplete(Address::from(address).into()))?;
+
+        let account = self
+            .accounts
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(Account::zero);
+
+        Ok((address, account))
+    }
+}
+
+/// Returns the root of an account, where the account's subtree is:
+///
+/// ```text
+///            root
+///          /      \
+///         *        *
+///        / \      / \
+///       *  nonce value padding
+///      / \
+///    pk[0..32] pk[32..48]
+/// ```
+pub fn account_root(account: &Account) -> H256 {
+    account_node(account, 1).expect("1 is the root of the account")
+}
+
+/// Returns the node at `index` within the account's subtree, where `1` is the account root.
+fn account_node(account: &Account, index: u64) -> Option<H256> {
+    let mut buf = [0u8; 128];
+    buf[0..48].copy_from_slice(&account.pubkey.as_bytes());
+    buf[64..72].copy_from_slice(&account.nonce.to_le_bytes());
+    buf[96..104].copy_from_slice(&account.value.to_le_bytes());
+
+    let chunk = |i: usize| H256::new(array_ref![buf, i * 32, 32]);
+
+    match index {
+        1 => Some(hash_pair(
+            &account_node(account, 2)?,
+            &account_node(account, 3)?,
+        )),
+        2 => Some(hash_pair(&account_node(account, 4)?, &chunk(2))),
+        3 => Some(hash_pair(&chunk(3), &H256::new(&[0u8; 32]))),
+        4 => Some(hash_pair(&chunk(0), &chunk(1))),
+        5 => Some(chunk(2)),
+        6 => Some(chunk(3)),
+        7 => Some(H256::new(&[0u8; 32])),
+        8 => Some(chunk(0)),
+        9 => Some(chunk(1)),
+        _ => None,
+    }
+}
+
+pub(crate) fn hash_pair(left: &H256, right: &H256) -> H256 {
+    let mut buf = [0u8; 64];
+    buf[0..32].copy_from_slice(left.as_bytes());
+    buf[32..64].copy_from_slice(right.as_bytes());
+    hash(&mut buf);
+    H256::new(array_ref![buf, 0, 32])
+}
+
+/// Hashes each pair of siblings into their parent. The pairs are independent, so they are hashed
+/// in parallel, and the parents are returned in the same order as the pairs.
+pub(crate) fn hash_pairs(pairs: &[(H256, H256)]) -> Vec<H256> {
+    pairs
+        .par_iter()
+        .map(|(left, right)| hash_pair(left, right))
+        .collect()
+}
+
+impl State for SparseMerkleTree {
+    fn root(&mut self) -> Result<[u8; 32], sheth::error::Error> {
+        Ok(SparseMerkleTree::root(self))
+    }
+
+    fn value(&self, address: Address) -> Result<u64, sheth::error::Error> {
+        Ok(self.account_or_zero(address)?.1.value)
+    }
+
+    fn nonce(&self, address: Address) -> Result<u64, sheth::error::Error> {
+        Ok(self.account_or_zero(address)?.1.nonce)
+    }
+
+    fn add_value(&mut self, address: Address, amount: u64) -> Result<u64, sheth::error::Error> {
+        let (address, mut account) = self.account_or_zero(address)?;
+
+        account.value = account
+            .value
+            .checked_add(amount)
+            .ok_or(sheth::error::Error::Overflow)?;
+
+        let value = account.value;
+        self.set(address, Some(account));
+
+        Ok(value)
+    }
+
+    fn sub_value(&mut self, address: Address, amount: u64) -> Result<u64, sheth::error::Error> {
+        let (address, mut account) = self.account_or_zero(address)?;
+
+        account.value = account
+            .value
+            .checked_sub(amount)
+            .ok_or(sheth::error::Error::Overflow)?;
+
+        let value = account.value;
+        self.set(address, Some(account));
+
+        Ok(value)
+    }
+
+    fn inc_nonce(&mut self, address: Address) -> Result<u64, sheth::error::Error> {
+        let (address, mut account) = self.account_or_zero(address)?;
+
+        account.nonce = account
+            .nonce
+            .checked_add(1)
+            .ok_or(sheth::error::Error::Overflow)?;
+
+        let nonce = account.nonce;
+        self.set(address, Some(account));
+
+        Ok(nonce)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::accounts::random_accounts;
+    use crate::proof::h256::zh;
+    use crate::proof::uncompressed::generate as generate_uncompressed_proof;
+    use crate::transactions;
+    use imp::Imp;
+    use sheth::process::process_transactions;
+    use sheth::u264::U264;
+
+    #[test]
+    fn zero_account_matches_zero_hash() {
+        assert_eq!(account_root(&Account::zero()), zh(0));
+        assert_eq!(SparseMerkleTree::new(4).root(), *zh(4).as_bytes());
+    }
+
+    #[test]
+    fn proof_matches_uncompressed_generation() {
+        let accounts = random_accounts(5, 8).unwrap();
+        let tree = SparseMerkleTree::from_accounts(accounts.clone(), 8).unwrap();
+        let addresses: Vec<U256> = accounts.iter().map(|a| a.0).collect();
+
+        assert_eq!(
+            tree.proof(&addresses).unwrap(),
+            generate_uncompressed_proof(accounts, 8)
+        );
+    }
+
+    #[test]
+    fn partial_proof_authenticates_root() {
+        let accounts = random_accounts(10, 256).unwrap();
+        let tree = SparseMerkleTree::from_accounts(accounts.clone(), 256).unwrap();
+
+        let addresses = vec![accounts[3].0, accounts[7].0, U256::from(12345)];
+        let mut proof = tree.proof(&addresses).unwrap().to_bytes();
+        let mut mem = Imp::<U264>::new(&mut proof, 259);
+
+        assert_eq!(mem.root(), tree.root());
+    }
+
+    #[test]
+    fn multiproof_of_arbitrary_nodes() {
+        let accounts = random_accounts(4, 8).unwrap();
+        let tree = SparseMerkleTree::from_accounts(accounts.clone(), 8).unwrap();
+        let leaf = |a: &AddressedAccount| (U512::one() << 8) + U512::from(a.0);
+
+        // The nonce and value of one account, the root of another, and a branch which may or may
+        // not be an ancestor of either.
+        let indexes = vec![
+            (leaf(&accounts[0]) << 2) + 1.into(),
+            (leaf(&accounts[0]) << 2) + 2.into(),
+            leaf(&accounts[1]),
+            U512::from(5),
+        ];
+
+        let proof = tree.multiproof(&indexes).unwrap();
+        assert_eq!(proof.indexes, alpha_sort(&proof.indexes));
+
+        let mut bytes = proof.to_bytes();
+        let mut mem = Imp::<U264>::new(&mut bytes, 11);
+        let address = Address::from(accounts[0].0);
+
+        assert_eq!(mem.nonce(address), Ok(accounts[0].1.nonce));
+        assert_eq!(mem.value(address), Ok(accounts[0].1.value));
+        assert_eq!(mem.root(), tree.root());
+
+        assert_eq!(
+            tree.multiproof(&[(leaf(&accounts[0]) << 3) + 2.into()]),
+            Err(Error::IndexInvalid((leaf(&accounts[0]) << 3) + 2.into()))
+        );
+        assert_eq!(
+            tree.multiproof(&[U512::zero()]),
+            Err(Error::IndexInvalid(U512::zero()))
+        );
+    }
+
+    #[test]
+    fn from_accounts_matches_inserts() {
+        for (n, height) in [(0, 8), (1, 1), (50, 8), (300, 256)].iter() {
+            let accounts = random_accounts(*n, *height).unwrap();
+            let tree = SparseMerkleTree::from_accounts(accounts.clone(), *height).unwrap();
+
+            let mut inserted = SparseMerkleTree::new(*height);
+            for AddressedAccount(address, account) in accounts {
+                inserted.insert(address, account).unwrap();
+            }
+
+            assert_eq!(tree.nodes(), inserted.nodes());
+            assert_eq!(tree.root(), inserted.root());
+        }
+
+        let accounts = random_accounts(2, 8).unwrap();
+        assert_eq!(
+            SparseMerkleTree::from_accounts(vec![accounts[0].clone(), accounts[0].clone()], 8)
+                .err(),
+            Some(Error::AccountExists(accounts[0].0))
+        );
+    }
+
+    #[test]
+    fn insert_update_delete() {
+        let accounts = random_accounts(3, 16).unwrap();
+        let mut tree = SparseMerkleTree::from_accounts(accounts[0..2].to_vec(), 16).unwrap();
+        let before = tree.root();
+
+        let AddressedAccount(address, account) = accounts[2].clone();
+        assert_eq!(
+            tree.insert(accounts[0].0, account.clone()),
+            Err(Error::AccountExists(accounts[0].0))
+        );
+        assert_eq!(tree.insert(address, account.clone()), Ok(()));
+        assert_eq!(
+            tree.root(),
+            SparseMerkleTree::from_accounts(accounts, 16)
+                .unwrap()
+                .root()
+        );
+
+        let mut updated = account.clone();
+        updated.value += 1;
+        assert_eq!(tree.update(address, updated).unwrap().value, account.value);
+        assert_eq!(tree.get(&address).unwrap().value, account.value + 1);
+
+        assert!(tree.delete(address).is_ok());
+        assert_eq!(tree.root(), before);
+        assert!(tree.delete(address).is_err());
+        assert_eq!(
+            tree.insert(U256::one() << 16, account),
+            Err(Error::AddressOutOfRange(U256::one() << 16))
+        );
+    }
+
+    #[test]
+    fn process_transactions_matches_imp() {
+        let accounts = random_accounts(4, 256).unwrap();
+        let txs = transactions::generate(10, accounts.clone());
+        let mut tree = SparseMerkleTree::from_accounts(accounts.clone(), 256).unwrap();
+
+        let addresses: Vec<U256> = accounts.iter().map(|a| a.0).collect();
+        let mut proof = tree.proof(&addresses).unwrap().to_bytes();
+        let mut mem = Imp::<U264>::new(&mut proof, 259);
+
+        assert_eq!(process_transactions(&mut mem, &txs), Ok(()));
+        assert_eq!(process_transactions(&mut tree, &txs), Ok(()));
+        assert_eq!(mem.root(), SparseMerkleTree::root(&tree));
+    }
+}
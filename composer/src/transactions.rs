@@ -1,40 +1,157 @@
 use crate::accounts::AddressedAccount;
+use crate::workload::{Invalid, Sampler, Workload};
+use bigint::U256;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use sheth::transaction::{Transaction, Transfer};
+use std::cmp::min;
+use std::collections::HashSet;
 
 /// Generate `n` number of transactions between `accounts`.
-pub fn generate(n: usize, mut accounts: Vec<AddressedAccount>) -> Vec<Transaction> {
-    let mut rng = StdRng::seed_from_u64(42);
+pub fn generate(n: usize, accounts: Vec<AddressedAccount>) -> Vec<Transaction> {
+    generate_with_workload(n, accounts, &Workload::default())
+}
+
+/// Generate `n` number of transactions between `accounts`, shaped by `workload`.
+pub fn generate_with_workload(
+    n: usize,
+    mut accounts: Vec<AddressedAccount>,
+    workload: &Workload,
+) -> Vec<Transaction> {
+    let mut rng = StdRng::seed_from_u64(workload.seed);
+    let sampler = Sampler::new(&workload.distribution, accounts.len());
 
     let mut transactions: Vec<Transaction> = vec![];
 
     for _ in 0..n {
-        let to = rng.gen_range(0, accounts.len());
-        let from = rng.gen_range(0, accounts.len());
-
-        let tx = Transaction::Transfer(Transfer {
-            to: accounts[to].0.into(),
-            from: accounts[from].0.into(),
-            nonce: accounts[from].1.nonce,
-            amount: rng.gen_range(0, accounts[from].1.value),
-            signature: [0u8; 96],
-        });
-
-        match &tx {
-            Transaction::Transfer(t) => {
-                accounts[from].1.nonce += 1;
-                accounts[from].1.value -= t.amount;
-                accounts[to].1.value += t.amount;
+        let (to, from) = match workload.self_transfer_rate {
+            None => {
+                let to = sampler.sample(&mut rng);
+                let from = sampler.sample(&mut rng);
+                (to, from)
+            }
+            Some(rate) => {
+                let from = sampler.sample(&mut rng);
+                let to = if accounts.len() == 1 || rng.gen::<f64>() < rate {
+                    from
+                } else {
+                    sampler.sample_other(&mut rng, from)
+                };
+                (to, from)
+            }
+        };
+
+        let mut kind = if workload.invalid_rate > 0.0 && rng.gen::<f64>() < workload.invalid_rate {
+            choose(&mut rng, &workload.invalid)
+        } else {
+            None
+        };
+
+        let tx = match kind.and_then(|kind| invalid(&mut rng, kind, &accounts, to, from)) {
+            Some(tx) => tx,
+            None => {
+                kind = None;
+
+                // Cap the amount so that the recipient's balance can't overflow.
+                let amount = workload.amount.sample(&mut rng, accounts[from].1.value);
+                let room = u64::MAX - accounts[to].1.value;
+
+                Transfer {
+                    to: accounts[to].0.into(),
+                    from: accounts[from].0.into(),
+                    nonce: accounts[from].1.nonce,
+                    amount: if to == from {
+                        amount
+                    } else {
+                        min(amount, room)
+                    },
+                    signature: [0u8; 96],
+                }
             }
-            _ => unreachable!(),
+        };
+
+        // Only transactions which are applied are tracked. The others are either skipped or cause
+        // the whole block to be rejected.
+        if kind.is_none() || kind == Some(Invalid::Signature) {
+            accounts[from].1.nonce += 1;
+            accounts[from].1.value -= tx.amount;
+            accounts[to].1.value += tx.amount;
         }
 
-        transactions.push(tx);
+        transactions.push(Transaction::Transfer(tx));
     }
 
     transactions
 }
 
+fn choose<R: Rng>(rng: &mut R, kinds: &[Invalid]) -> Option<Invalid> {
+    if kinds.is_empty() {
+        None
+    } else {
+        Some(kinds[rng.gen_range(0, kinds.len())])
+    }
+}
+
+/// Builds an invalid transfer of kind `kind` from `accounts[from]`. Returns `None` if the accounts
+/// don't allow it, e.g. if the sender has no balance to overflow the recipient with.
+fn invalid<R: Rng>(
+    rng: &mut R,
+    kind: Invalid,
+    accounts: &[AddressedAccount],
+    to: usize,
+    from: usize,
+) -> Option<Transfer> {
+    let sender = &accounts[from].1;
+
+    let mut tx = Transfer {
+        to: accounts[to].0.into(),
+        from: accounts[from].0.into(),
+        nonce: sender.nonce,
+        amount: 0,
+        signature: [0u8; 96],
+    };
+
+    match kind {
+        Invalid::Nonce => tx.nonce = sender.nonce.wrapping_add(rng.gen_range(1, 1000)),
+        Invalid::Overdraft => tx.amount = sender.value.checked_add(rng.gen_range(1, 1000))?,
+        Invalid::Overflow => {
+            let (to, recipient) = accounts
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != from)
+                .max_by_key(|(_, a)| a.1.value)?;
+
+            let room = u64::MAX - recipient.1.value;
+            if sender.value <= room {
+                return None;
+            }
+
+            tx.to = accounts[to].0.into();
+            tx.amount = rng.gen_range(room, sender.value) + 1;
+        }
+        Invalid::UnknownAddress => {
+            tx.to = unknown_address(rng, accounts)?.into();
+            tx.amount = min(1, sender.value);
+        }
+        Invalid::Signature => {
+            tx.amount = min(1, sender.value);
+            rng.fill(&mut tx.signature[..]);
+        }
+    }
+
+    Some(tx)
+}
+
+/// Picks an address which doesn't belong to any of `accounts`. The sibling of an account is
+/// always within the tree, and when it's empty, only its root is included in the proof.
+fn unknown_address<R: Rng>(rng: &mut R, accounts: &[AddressedAccount]) -> Option<U256> {
+    let known: HashSet<U256> = accounts.iter().map(|a| a.0).collect();
+    let start = rng.gen_range(0, accounts.len());
+
+    (0..accounts.len())
+        .map(|i| accounts[(start + i) % accounts.len()].0 ^ U256::one())
+        .find(|address| !known.contains(address))
+}
+
 /// Convert an array of `Transaction` to an unaligned array of `u8`.
 pub fn serialize(transactions: &[Transaction]) -> Vec<u8> {
     let mut bytes = transactions.len().to_le_bytes()[0..4].to_vec();
@@ -54,3 +171,44 @@ pub fn serialize(transactions: &[Transaction]) -> Vec<u8> {
 
     bytes
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::accounts::random_accounts;
+
+    fn self_transfers(rate: f64) -> usize {
+        let workload = Workload {
+            self_transfer_rate: Some(rate),
+            ..Workload::default()
+        };
+
+        generate_with_workload(50, random_accounts(5, 16).unwrap(), &workload)
+            .iter()
+            .filter(|tx| match tx {
+                Transaction::Transfer(t) => t.to == t.from,
+                _ => unreachable!(),
+            })
+            .count()
+    }
+
+    #[test]
+    fn self_transfer_rate() {
+        assert_eq!(self_transfers(0.0), 0);
+        assert_eq!(self_transfers(1.0), 50);
+    }
+
+    #[test]
+    fn seed_changes_transactions() {
+        let accounts = random_accounts(5, 16).unwrap();
+        let workload = Workload {
+            seed: 7,
+            ..Workload::default()
+        };
+
+        let a = serialize(&generate(10, accounts.clone()));
+        let b = serialize(&generate_with_workload(10, accounts, &workload));
+
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::accounts::{AddressedAccount, Addresses};
+use rand::Rng;
+use std::str::FromStr;
+
+/// Describes the shape of the randomly generated transactions in a blob.
+///
+/// The default workload reproduces the original generator: a seed of `42`, senders and
+/// recipients picked uniformly, and amounts picked uniformly from the sender's balance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Workload {
+    /// Seed for both the random accounts and the random transactions
+    pub seed: u64,
+
+    /// How the addresses of the random accounts are assigned
+    pub addresses: Addresses,
+
+    /// How senders and recipients are picked from the accounts
+    pub distribution: Distribution,
+
+    /// Probability that a transaction is sent to its own sender. When `None`, the recipient is
+    /// picked independently of the sender.
+    pub self_transfer_rate: Option<f64>,
+
+    /// How the amount of each transfer is picked
+    pub amount: Amount,
+
+    /// Probability that a transaction is replaced by an invalid one
+    pub invalid_rate: f64,
+
+    /// The kinds of invalid transactions that may be injected
+    pub invalid: Vec<Invalid>,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload {
+            seed: 42,
+            addresses: Addresses::Random,
+            distribution: Distribution::Uniform,
+            self_transfer_rate: None,
+            amount: Amount::Uniform,
+            invalid_rate: 0.0,
+            invalid: Invalid::all(),
+        }
+    }
+}
+
+impl Workload {
+    /// Returns `true` if invalid transactions of kind `kind` may be injected.
+    pub fn injects(&self, kind: Invalid) -> bool {
+        self.invalid_rate > 0.0 && self.invalid.contains(&kind)
+    }
+
+    /// Adjusts the initial accounts so that every enabled kind of invalid transaction can be
+    /// generated. Overflowing a recipient requires an account with the maximum balance, so the
+    /// last account's balance is raised to `u64::MAX`.
+    pub fn prepare(&self, accounts: &mut [AddressedAccount]) {
+        if self.injects(Invalid::Overflow) && accounts.len() > 1 {
+            if let Some(last) = accounts.last_mut() {
+                last.1.value = u64::MAX;
+            }
+        }
+    }
+}
+
+/// A kind of invalid transaction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Invalid {
+    /// The nonce doesn't match the sender's nonce, so the transaction is skipped
+    Nonce,
+
+    /// The amount is larger than the sender's balance, so the block is rejected
+    Overdraft,
+
+    /// The recipient's balance overflows, so the block is rejected
+    Overflow,
+
+    /// The recipient isn't included in the proof, so the block is rejected
+    UnknownAddress,
+
+    /// The signature is random. Signatures aren't verified yet, so the transaction is applied.
+    Signature,
+}
+
+impl Invalid {
+    pub fn all() -> Vec<Invalid> {
+        vec![
+            Invalid::Nonce,
+            Invalid::Overdraft,
+            Invalid::Overflow,
+            Invalid::UnknownAddress,
+            Invalid::Signature,
+        ]
+    }
+}
+
+/// Distribution of the accounts that are touched by transactions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Distribution {
+    /// Every account is equally likely
+    Uniform,
+
+    /// The `k`th account is picked with a probability proportional to `1 / k^exponent`
+    Zipf(f64),
+
+    /// With probability `rate`, one of the first `accounts` accounts is picked. Otherwise any
+    /// account is picked.
+    Hot { accounts: usize, rate: f64 },
+}
+
+/// Distribution of the amounts that are transferred. Amounts are always capped by the sender's
+/// balance, so that every transaction remains valid.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Amount {
+    /// Any amount less than the sender's balance
+    Uniform,
+
+    /// Always the same amount
+    Fixed(u64),
+
+    /// Any amount in `[min, max)`
+    Range(u64, u64),
+}
+
+/// Number of samples drawn by `Sampler::sample_other` before it falls back to a uniform pick.
+const ATTEMPTS: usize = 16;
+
+/// Picks account indexes according to a `Distribution`.
+pub(crate) struct Sampler {
+    distribution: Distribution,
+    n: usize,
+    cdf: Vec<f64>,
+}
+
+impl Sampler {
+    pub(crate) fn new(distribution: &Distribution, n: usize) -> Self {
+        let cdf = match distribution {
+            Distribution::Zipf(exponent) => {
+                let mut sum = 0.0;
+                (0..n)
+                    .map(|k| {
+                        sum += 1.0 / ((k + 1) as f64).powf(*exponent);
+                        sum
+                    })
+                    .collect()
+            }
+            _ => vec![],
+        };
+
+        Sampler {
+            distribution: distribution.clone(),
+            n,
+            cdf,
+        }
+    }
+
+    pub(crate) fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        match self.distribution {
+            Distribution::Uniform => rng.gen_range(0, self.n),
+            Distribution::Zipf(_) => {
+                let x = rng.gen::<f64>() * self.cdf[self.n - 1];
+                match self.cdf.binary_search_by(|p| p.partial_cmp(&x).unwrap()) {
+                    Ok(i) | Err(i) => std::cmp::min(i, self.n - 1),
+                }
+            }
+            Distribution::Hot { accounts, rate } => {
+                let hot = std::cmp::max(1, std::cmp::min(accounts, self.n));
+
+                if rng.gen::<f64>() < rate {
+                    rng.gen_range(0, hot)
+                } else {
+                    rng.gen_range(0, self.n)
+                }
+            }
+        }
+    }
+
+    /// Picks an account other than `except`, which requires at least two accounts. A distribution
+    /// may (almost) only pick `except`, e.g. a single hot account, so after a few attempts the
+    /// account is picked uniformly from the others instead.
+    pub(crate) fn sample_other<R: Rng>(&self, rng: &mut R, except: usize) -> usize {
+        for _ in 0..ATTEMPTS {
+            let i = self.sample(rng);
+            if i != except {
+                return i;
+            }
+        }
+
+        match rng.gen_range(0, self.n - 1) {
+            i if i < except => i,
+            i => i + 1,
+        }
+    }
+}
+
+impl Amount {
+    pub(crate) fn sample<R: Rng>(&self, rng: &mut R, balance: u64) -> u64 {
+        let amount = match *self {
+            Amount::Uniform if balance == 0 => 0,
+            Amount::Uniform => rng.gen_range(0, balance),
+            Amount::Fixed(amount) => amount,
+            Amount::Range(min, max) if min >= max => min,
+            Amount::Range(min, max) => rng.gen_range(min, max),
+        };
+
+        std::cmp::min(amount, balance)
+    }
+}
+
+/// Parses `uniform`, `zipf:<exponent>`, or `hot:<accounts>:<rate>`.
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+
+        match parts.as_slice() {
+            ["uniform"] => Ok(Distribution::Uniform),
+            ["zipf", exponent] => Ok(Distribution::Zipf(parse_exponent(exponent)?)),
+            ["hot", accounts, rate] => Ok(Distribution::Hot {
+                accounts: parse(accounts)?,
+                rate: parse_rate(rate)?,
+            }),
+            _ => Err(format!("unknown distribution: {}", s)),
+        }
+    }
+}
+
+/// Parses `uniform`, `fixed:<amount>`, or `range:<min>:<max>`.
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+
+        match parts.as_slice() {
+            ["uniform"] => Ok(Amount::Uniform),
+            ["fixed", amount] => Ok(Amount::Fixed(parse(amount)?)),
+            ["range", min, max] => Ok(Amount::Range(parse(min)?, parse(max)?)),
+            _ => Err(format!("unknown amount distribution: {}", s)),
+        }
+    }
+}
+
+/// Parses `nonce`, `overdraft`, `overflow`, `unknown-address`, or `signature`.
+impl FromStr for Invalid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nonce" => Ok(Invalid::Nonce),
+            "overdraft" => Ok(Invalid::Overdraft),
+            "overflow" => Ok(Invalid::Overflow),
+            "unknown-address" => Ok(Invalid::UnknownAddress),
+            "signature" => Ok(Invalid::Signature),
+            _ => Err(format!("unknown kind of invalid transaction: {}", s)),
+        }
+    }
+}
+
+/// Parses a comma separated list of kinds of invalid transactions.
+pub fn parse_invalid(s: &str) -> Result<Vec<Invalid>, String> {
+    s.split(',').map(|kind| kind.trim().parse()).collect()
+}
+
+/// Parses a probability between `0` and `1`.
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = parse(s)?;
+
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("rate must be between 0 and 1: {}", s))
+    }
+}
+
+/// Parses a finite, positive exponent.
+fn parse_exponent(s: &str) -> Result<f64, String> {
+    let exponent: f64 = parse(s)?;
+
+    if exponent.is_finite() && exponent > 0.0 {
+        Ok(exponent)
+    } else {
+        Err(format!("exponent must be a positive number: {}", s))
+    }
+}
+
+fn parse<T: FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number: {}", s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn histogram(distribution: Distribution, n: usize, samples: usize) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let sampler = Sampler::new(&distribution, n);
+
+        (0..samples).fold(vec![0; n], |mut acc, _| {
+            acc[sampler.sample(&mut rng)] += 1;
+            acc
+        })
+    }
+
+    #[test]
+    fn zipf_favors_first_accounts() {
+        let counts = histogram(Distribution::Zipf(1.5), 100, 10000);
+
+        assert!(counts[0] > counts[1]);
+        assert!(counts[1] > counts[10]);
+        assert!(counts[0] > 10000 / 4);
+    }
+
+    #[test]
+    fn hot_accounts() {
+        let counts = histogram(
+            Distribution::Hot {
+                accounts: 2,
+                rate: 0.9,
+            },
+            100,
+            10000,
+        );
+
+        assert!(counts[0] + counts[1] > 10000 * 8 / 10);
+    }
+
+    #[test]
+    fn sample_other_accounts() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let distributions = vec![
+            Distribution::Hot {
+                accounts: 1,
+                rate: 1.0,
+            },
+            Distribution::Zipf(1000.0),
+        ];
+
+        for distribution in distributions {
+            let sampler = Sampler::new(&distribution, 2);
+            for _ in 0..100 {
+                assert_eq!(sampler.sample_other(&mut rng, 0), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn amounts_are_capped_by_balance() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        assert_eq!(Amount::Fixed(10).sample(&mut rng, 5), 5);
+        assert_eq!(Amount::Uniform.sample(&mut rng, 0), 0);
+        assert_eq!(Amount::Range(3, 4).sample(&mut rng, 100), 3);
+    }
+
+    #[test]
+    fn parse_distributions() {
+        assert_eq!("uniform".parse(), Ok(Distribution::Uniform));
+        assert_eq!("zipf:1.1".parse(), Ok(Distribution::Zipf(1.1)));
+        assert_eq!(
+            "hot:10:0.5".parse(),
+            Ok(Distribution::Hot {
+                accounts: 10,
+                rate: 0.5
+            })
+        );
+        assert!("hot:10:2".parse::<Distribution>().is_err());
+        assert!("zipf".parse::<Distribution>().is_err());
+        assert!("zipf:NaN".parse::<Distribution>().is_err());
+        assert!("zipf:inf".parse::<Distribution>().is_err());
+        assert!("zipf:0".parse::<Distribution>().is_err());
+        assert!("zipf:-1".parse::<Distribution>().is_err());
+
+        assert_eq!("fixed:7".parse(), Ok(Amount::Fixed(7)));
+        assert_eq!("range:1:9".parse(), Ok(Amount::Range(1, 9)));
+        assert!("range:1".parse::<Amount>().is_err());
+
+        assert_eq!(
+            parse_invalid("nonce,unknown-address"),
+            Ok(vec![Invalid::Nonce, Invalid::UnknownAddress])
+        );
+        assert!(parse_invalid("nonce,").is_err());
+    }
+}
@@ -0,0 +1,10 @@
+#![no_main]
+use composer::blob::Blob;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Blobs are copied verbatim, so decoding must be the inverse of encoding
+    if let Ok(blob) = Blob::from_bytes(data, 256) {
+        assert_eq!(blob.to_bytes(), data);
+    }
+});
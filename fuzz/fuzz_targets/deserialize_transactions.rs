@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use sheth::deserialize_transactions;
+use std::convert::TryInto;
+
+fuzz_target!(|data: &[u8]| {
+    // The count is read from the data like `process_data_blob` does, so it usually disagrees with
+    // the length of the data
+    let count = match data.get(0..4) {
+        Some(bytes) => u32::from_le_bytes(bytes.try_into().unwrap()) as usize,
+        None => 0,
+    };
+
+    if let Ok(transactions) = deserialize_transactions(data, count) {
+        assert_eq!(transactions.len(), count);
+    }
+});
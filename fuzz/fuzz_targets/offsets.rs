@@ -0,0 +1,16 @@
+#![no_main]
+use composer::proof::offsets::{calculate, indexes};
+use libfuzzer_sys::fuzz_target;
+use std::convert::TryInto;
+
+fuzz_target!(|data: &[u8]| {
+    let offsets: Vec<u64> = data
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+
+    // Each tree is described by exactly one list of offsets
+    if let Ok(indexes) = indexes(&offsets) {
+        assert_eq!(calculate(indexes), offsets);
+    }
+});
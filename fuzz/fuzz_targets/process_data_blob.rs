@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use sheth::process_data_blob;
+use std::convert::TryInto;
+
+// The input is the pre-state root followed by the blob, so that blobs which match their root reach
+// the transactions.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 32 {
+        return;
+    }
+
+    let pre_state_root: [u8; 32] = data[..32].try_into().unwrap();
+    let mut blob = data[32..].to_vec();
+
+    let _ = process_data_blob(&mut blob, &pre_state_root);
+});
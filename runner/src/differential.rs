@@ -0,0 +1,171 @@
+//! Differential tests which execute random blobs both natively and with the compiled `sheth.wasm`,
+//! to catch bugs that only occur in `no_std` builds (e.g. in `U264` or the allocator).
+
+use crate::error::Error;
+use crate::Runner;
+use composer::accounts::Addresses;
+use composer::blob::{self, Blob};
+use composer::workload::Workload;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sheth::host::{InvalidBlock, MockHost, INVALID_BLOCK};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use wasmi::{Engine, Module};
+
+/// Number of random blobs to compare. The interpreter is slow without optimizations, so only a
+/// few blobs are compared in debug builds.
+const CASES: u64 = if cfg!(debug_assertions) { 16 } else { 128 };
+
+/// Size of the input buffer of `sheth.wasm`, larger blobs can't be executed by it.
+const BUFFER: usize = 42000;
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    PostState([u8; 32]),
+
+    /// The block was rejected with the code of its error
+    Reverted(u32),
+
+    /// Only the wasm build can trap, so this never matches a native outcome
+    Trapped(String),
+}
+
+/// Reads `sheth.wasm`, built with `make build-wasm`. Panics if a source file of `sheth` changed
+/// since, or if the module doesn't handle invalid blocks according to `INVALID_BLOCK`, so that the
+/// tests don't pass against a stale build.
+pub(crate) fn sheth_wasm() -> Vec<u8> {
+    let path = "../target/wasm32-unknown-unknown/release/sheth.wasm";
+    let built = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .expect("sheth.wasm to be built");
+
+    if let Some(source) = changed_since(Path::new("../src"), built) {
+        panic!(
+            "sheth.wasm is older than {}, rebuild it with `make build-wasm`",
+            source.display()
+        );
+    }
+
+    let wasm = fs::read(path).unwrap();
+    let module = Module::new(&Engine::default(), &wasm[..]).unwrap();
+    let reverts = module
+        .imports()
+        .any(|import| import.name() == "sheth_revert");
+
+    assert_eq!(
+        reverts,
+        INVALID_BLOCK == InvalidBlock::Revert,
+        "sheth.wasm must be built with the `revert` feature iff the runner is"
+    );
+
+    wasm
+}
+
+/// Returns a file in `dir` or its subdirectories which was modified after `time`, if any.
+fn changed_since(dir: &Path, time: SystemTime) -> Option<PathBuf> {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let changed = if metadata.is_dir() {
+            changed_since(&path, time)
+        } else if metadata.modified().unwrap() > time {
+            Some(path)
+        } else {
+            None
+        };
+
+        if changed.is_some() {
+            return changed;
+        }
+    }
+
+    None
+}
+
+/// Builds a random blob, and the pre-state root to execute it on. Some blobs contain invalid
+/// transactions, and some pre-state roots don't match the blob, so that both failures and
+/// successes are compared.
+fn case(seed: u64) -> (Blob, [u8; 32]) {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Accounts with sequential addresses share most of their branches, so more of them fit into
+    // the input buffer
+    let (addresses, accounts) = match rng.gen_bool(0.5) {
+        true => (Addresses::Sequential, rng.gen_range(1, 9)),
+        false => (Addresses::Random, rng.gen_range(1, 3)),
+    };
+
+    let workload = Workload {
+        seed,
+        addresses,
+        invalid_rate: [0.0, 0.0, 0.2, 1.0][rng.gen_range(0, 4)],
+        ..Workload::default()
+    };
+
+    let blob = blob::generate_with_workload(accounts, rng.gen_range(0, 8), 256, &workload)
+        .expect("accounts to fit into the tree");
+    let (mut pre_state, _) = blob::process(&blob, 256);
+
+    if rng.gen_bool(0.125) {
+        pre_state[rng.gen_range(0, 32)] ^= 1;
+    }
+
+    (blob, pre_state)
+}
+
+/// Executes the blob natively with `sheth::run`, which handles invalid blocks the same way as the
+/// wasm build. Returns the outcome and whether the block was invalid.
+fn native(blob: &[u8], pre_state: [u8; 32]) -> (Outcome, bool) {
+    let mut host = MockHost::new(pre_state, blob.to_vec());
+    let result = sheth::run(&mut host);
+    let invalid = result.is_err();
+
+    match (host.post_state, result) {
+        (Some(post_state), _) => (Outcome::PostState(post_state), invalid),
+        (None, Err(e)) => (Outcome::Reverted(e.code()), invalid),
+        (None, Ok(())) => panic!("a valid block must save its post-state root"),
+    }
+}
+
+fn wasm(runner: &Runner, blob: &[u8], pre_state: [u8; 32]) -> Outcome {
+    match runner.execute(pre_state, blob) {
+        Ok(post_state) => Outcome::PostState(post_state),
+        Err(Error::Reverted(code)) => Outcome::Reverted(code),
+        Err(e) => Outcome::Trapped(format!("{:?}", e)),
+    }
+}
+
+#[test]
+fn native_and_wasm_agree() {
+    let runner = Runner::new(&sheth_wasm()).unwrap();
+
+    let mut invalid = vec![];
+
+    for seed in 0..CASES {
+        let (blob, pre_state) = case(seed);
+        let blob = blob.to_bytes();
+
+        if blob.len() > BUFFER {
+            continue;
+        }
+
+        let (native, rejected) = native(&blob, pre_state);
+        assert_eq!(
+            native,
+            wasm(&runner, &blob, pre_state),
+            "seed {} diverges: pre-state 0x{}, blob 0x{}",
+            seed,
+            hex::encode(pre_state),
+            hex::encode(&blob)
+        );
+
+        invalid.push(rejected);
+    }
+
+    // Make sure both valid and invalid blocks were compared
+    assert!(invalid.contains(&true));
+    assert!(invalid.contains(&false));
+}
@@ -0,0 +1,35 @@
+use wasmi::core::Trap;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The module couldn't be compiled, or its imports couldn't be resolved
+    ModuleInvalid(String),
+
+    /// The module doesn't export a `main` function
+    MainMissing,
+
+    /// Execution trapped (e.g. a panic in `sheth`)
+    Trap(String),
+
+    /// The module returned without calling `eth2_savePostStateRoot`
+    PostStateMissing,
+
+    /// The module rejected the block by calling `sheth_revert` with an error code (see
+    /// `sheth::error::Error::code`)
+    Reverted(u32),
+}
+
+impl From<wasmi::Error> for Error {
+    fn from(e: wasmi::Error) -> Self {
+        match e {
+            wasmi::Error::Trap(trap) => trap.into(),
+            e => Error::ModuleInvalid(e.to_string()),
+        }
+    }
+}
+
+impl From<Trap> for Error {
+    fn from(trap: Trap) -> Self {
+        Error::Trap(trap.to_string())
+    }
+}
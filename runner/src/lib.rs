@@ -0,0 +1,344 @@
+#[cfg(test)]
+mod differential;
+pub mod error;
+pub mod meter;
+
+use error::Error;
+use meter::Cost;
+use wasmi::core::Trap;
+use wasmi::{Caller, Config, Engine, Extern, Linker, Module, Store};
+
+/// Executes an execution environment (e.g. `sheth.wasm`) the same way Scout does, by providing
+/// the `eth2` host functions that are declared in the `native` module of `sheth`. Execution is
+/// metered with fuel, which roughly counts the executed instructions.
+pub struct Runner {
+    engine: Engine,
+    module: Module,
+}
+
+/// The data which the host functions expose to the module.
+struct Environment {
+    pre_state: [u8; 32],
+    block_data: Vec<u8>,
+    post_state: Option<[u8; 32]>,
+
+    /// Each checkpoint reported by the module, along with the fuel consumed at that point
+    checkpoints: Vec<(u32, u64)>,
+
+    /// The error code the module reverted with, if any
+    reverted: Option<u32>,
+}
+
+impl Runner {
+    /// Compiles the module, so that it can execute any number of blocks.
+    pub fn new(wasm: &[u8]) -> Result<Self, Error> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm)?;
+
+        Ok(Runner { engine, module })
+    }
+
+    /// Executes `block_data` on top of `pre_state` and returns the post-state root saved by the
+    /// module.
+    pub fn execute(&self, pre_state: [u8; 32], block_data: &[u8]) -> Result<[u8; 32], Error> {
+        let store = self.call(pre_state, block_data)?;
+        store.data().post_state.ok_or(Error::PostStateMissing)
+    }
+
+    /// Executes `block_data` on top of `pre_state` like `execute`, and returns the fuel consumed
+    /// by the block.
+    pub fn meter(&self, pre_state: [u8; 32], block_data: &[u8]) -> Result<Cost, Error> {
+        let store = self.call(pre_state, block_data)?;
+        let post_state = store.data().post_state.ok_or(Error::PostStateMissing)?;
+        let total = store.fuel_consumed().unwrap_or_default();
+
+        Ok(Cost {
+            post_state,
+            total,
+            breakdown: meter::breakdown(&store.data().checkpoints, total),
+        })
+    }
+
+    /// Calls the module's `main` function, and returns the store with the resulting environment.
+    fn call(&self, pre_state: [u8; 32], block_data: &[u8]) -> Result<Store<Environment>, Error> {
+        let environment = Environment {
+            pre_state,
+            block_data: block_data.to_vec(),
+            post_state: None,
+            checkpoints: vec![],
+            reverted: None,
+        };
+
+        let mut store = Store::new(&self.engine, environment);
+        store
+            .add_fuel(u64::MAX)
+            .map_err(|e| Error::ModuleInvalid(e.to_string()))?;
+        let linker = linker(&self.engine)?;
+
+        let instance = linker
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+
+        let main = instance
+            .get_typed_func::<(), ()>(&store, "main")
+            .map_err(|_| Error::MainMissing)?;
+
+        // Reverting stops execution with a trap, which isn't an error of the module
+        let result = main.call(&mut store, ());
+
+        if let Some(code) = store.data().reverted {
+            return Err(Error::Reverted(code));
+        }
+
+        result?;
+
+        Ok(store)
+    }
+}
+
+/// Returns a linker which provides each of the `eth2` host functions, `sheth_checkpoint` which is
+/// only imported by metered builds of `sheth`, and `sheth_revert` which is only imported by builds
+/// with the `revert` feature.
+fn linker(engine: &Engine) -> Result<Linker<Environment>, Error> {
+    let mut linker = Linker::new(engine);
+
+    linker
+        .func_wrap(
+            "env",
+            "eth2_loadPreStateRoot",
+            |mut caller: Caller<'_, Environment>, offset: u32| -> Result<(), Trap> {
+                let root = caller.data().pre_state;
+                write(&mut caller, offset, &root)
+            },
+        )
+        .and_then(|l| {
+            l.func_wrap(
+                "env",
+                "eth2_blockDataSize",
+                |caller: Caller<'_, Environment>| -> u32 { caller.data().block_data.len() as u32 },
+            )
+        })
+        .and_then(|l| {
+            l.func_wrap(
+                "env",
+                "eth2_blockDataCopy",
+                |mut caller: Caller<'_, Environment>,
+                 output: u32,
+                 offset: u32,
+                 length: u32|
+                 -> Result<(), Trap> {
+                    let data = &caller.data().block_data;
+                    let (start, end) = (offset as usize, offset as usize + length as usize);
+
+                    if end > data.len() {
+                        return Err(Trap::new("block data copy out of bounds"));
+                    }
+
+                    let chunk = data[start..end].to_vec();
+                    write(&mut caller, output, &chunk)
+                },
+            )
+        })
+        .and_then(|l| {
+            l.func_wrap(
+                "env",
+                "eth2_savePostStateRoot",
+                |mut caller: Caller<'_, Environment>, offset: u32| -> Result<(), Trap> {
+                    let mut root = [0u8; 32];
+                    read(&caller, offset, &mut root)?;
+                    caller.data_mut().post_state = Some(root);
+                    Ok(())
+                },
+            )
+        })
+        .and_then(|l| {
+            l.func_wrap(
+                "env",
+                "sheth_checkpoint",
+                |mut caller: Caller<'_, Environment>, checkpoint: u32| {
+                    let fuel = caller.fuel_consumed().unwrap_or_default();
+                    caller.data_mut().checkpoints.push((checkpoint, fuel));
+                },
+            )
+        })
+        .and_then(|l| {
+            l.func_wrap(
+                "env",
+                "sheth_revert",
+                |mut caller: Caller<'_, Environment>, code: u32| -> Result<(), Trap> {
+                    caller.data_mut().reverted = Some(code);
+                    Err(Trap::new(format!("reverted with code {}", code)))
+                },
+            )
+        })
+        .map_err(|e| Error::ModuleInvalid(e.to_string()))?;
+
+    Ok(linker)
+}
+
+fn read(caller: &Caller<'_, Environment>, offset: u32, buf: &mut [u8]) -> Result<(), Trap> {
+    memory(caller)?
+        .read(caller, offset as usize, buf)
+        .map_err(|e| Trap::new(e.to_string()))
+}
+
+fn write(caller: &mut Caller<'_, Environment>, offset: u32, buf: &[u8]) -> Result<(), Trap> {
+    memory(caller)?
+        .write(caller, offset as usize, buf)
+        .map_err(|e| Trap::new(e.to_string()))
+}
+
+fn memory(caller: &Caller<'_, Environment>) -> Result<wasmi::Memory, Trap> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Trap::new("module doesn't export a memory"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::differential::sheth_wasm;
+    use composer::blob;
+    use sheth::host::{InvalidBlock, INVALID_BLOCK};
+
+    // Saves the first 32 bytes of the block data as the post-state root, or the pre-state root if
+    // the block data is shorter.
+    const ECHO: &str = r#"
+        (module
+            (import "env" "eth2_loadPreStateRoot" (func $load (param i32)))
+            (import "env" "eth2_blockDataSize" (func $size (result i32)))
+            (import "env" "eth2_blockDataCopy" (func $copy (param i32 i32 i32)))
+            (import "env" "eth2_savePostStateRoot" (func $save (param i32)))
+            (memory (export "memory") 1)
+            (func (export "main")
+                (call $load (i32.const 0))
+                (call $copy (i32.const 32) (i32.const 0) (call $size))
+                (if (i32.ge_u (call $size) (i32.const 32))
+                    (then (call $save (i32.const 32)))
+                    (else (call $save (i32.const 0))))))
+    "#;
+
+    #[test]
+    fn host_functions() {
+        let runner = Runner::new(&wat::parse_str(ECHO).unwrap()).unwrap();
+
+        assert_eq!(runner.execute([1u8; 32], &[]), Ok([1u8; 32]));
+        assert_eq!(runner.execute([1u8; 32], &[2u8; 40]), Ok([2u8; 32]));
+    }
+
+    #[test]
+    fn module_errors() {
+        let silent = r#"(module (memory (export "memory") 1) (func (export "main")))"#;
+        let runner = Runner::new(&wat::parse_str(silent).unwrap()).unwrap();
+        assert_eq!(runner.execute([0u8; 32], &[]), Err(Error::PostStateMissing));
+
+        let trap = r#"(module (memory (export "memory") 1) (func (export "main") unreachable))"#;
+        let runner = Runner::new(&wat::parse_str(trap).unwrap()).unwrap();
+        assert!(matches!(
+            runner.execute([0u8; 32], &[]),
+            Err(Error::Trap(_))
+        ));
+
+        let unknown = r#"(module (import "env" "eth2_unknown" (func)) (func (export "main")))"#;
+        let runner = Runner::new(&wat::parse_str(unknown).unwrap()).unwrap();
+        assert!(matches!(
+            runner.execute([0u8; 32], &[]),
+            Err(Error::ModuleInvalid(_))
+        ));
+
+        assert!(Runner::new(&[0u8; 8]).is_err());
+    }
+
+    #[test]
+    fn revert() {
+        // Reverts instead of saving a post-state root, so nothing after the call is executed
+        let revert = r#"
+            (module
+                (import "env" "sheth_revert" (func $revert (param i32)))
+                (memory (export "memory") 1)
+                (func (export "main")
+                    (call $revert (i32.const 8))
+                    unreachable))
+        "#;
+
+        let runner = Runner::new(&wat::parse_str(revert).unwrap()).unwrap();
+        assert_eq!(runner.execute([0u8; 32], &[]), Err(Error::Reverted(8)));
+        assert_eq!(runner.meter([0u8; 32], &[]), Err(Error::Reverted(8)));
+    }
+
+    #[test]
+    fn meter_checkpoints() {
+        // Reports the checkpoints of a block with two transactions, with some work between them
+        let metered = r#"
+            (module
+                (import "env" "eth2_savePostStateRoot" (func $save (param i32)))
+                (import "env" "sheth_checkpoint" (func $checkpoint (param i32)))
+                (memory (export "memory") 1)
+                (func $work (param $n i32)
+                    (loop $next
+                        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                        (br_if $next (local.get $n))))
+                (func (export "main")
+                    (call $work (i32.const 10))
+                    (call $checkpoint (i32.const 0))
+                    (call $work (i32.const 100))
+                    (call $checkpoint (i32.const 1))
+                    (call $work (i32.const 20))
+                    (call $checkpoint (i32.const 2))
+                    (call $work (i32.const 30))
+                    (call $checkpoint (i32.const 2))
+                    (call $work (i32.const 100))
+                    (call $checkpoint (i32.const 3))
+                    (call $save (i32.const 0))))
+        "#;
+
+        let runner = Runner::new(&wat::parse_str(metered).unwrap()).unwrap();
+        let cost = runner.meter([0u8; 32], &[]).unwrap();
+        let breakdown = cost.breakdown.unwrap();
+
+        assert_eq!(breakdown.transactions.len(), 2);
+        assert!(breakdown.transactions[0] < breakdown.transactions[1]);
+        assert!(breakdown.decoding < breakdown.transactions[0]);
+        assert_eq!(breakdown.pre_state, breakdown.post_state);
+        assert_eq!(
+            breakdown.decoding
+                + breakdown.hashing()
+                + breakdown.transactions.iter().sum::<u64>()
+                + breakdown.rest,
+            cost.total
+        );
+
+        // Without checkpoints, only the total is known
+        let runner = Runner::new(&wat::parse_str(ECHO).unwrap()).unwrap();
+        let cost = runner.meter([1u8; 32], &[]).unwrap();
+        assert_eq!(cost.post_state, [1u8; 32]);
+        assert!(cost.total > 0);
+        assert_eq!(cost.breakdown, None);
+    }
+
+    #[test]
+    fn execute_sheth() {
+        let runner = Runner::new(&sheth_wasm()).unwrap();
+
+        let (blob, pre_state, post_state) = blob::generate_with_roots(2, 1, 256).unwrap();
+        let blob = blob.to_bytes();
+
+        assert_eq!(runner.execute(pre_state, &blob), Ok(post_state));
+        assert_eq!(
+            runner.meter(pre_state, &blob).unwrap().post_state,
+            post_state
+        );
+
+        let expected = match INVALID_BLOCK {
+            InvalidBlock::SavePreState => Ok([0u8; 32]),
+            InvalidBlock::Revert => Err(Error::Reverted(
+                sheth::error::Error::PreStateRootMismatch.code(),
+            )),
+        };
+        assert_eq!(runner.execute([0u8; 32], &blob), expected);
+    }
+}
@@ -0,0 +1,134 @@
+use clap::{App, Arg};
+use runner::error::Error;
+use runner::meter::Cost;
+use runner::Runner;
+use sheth::chain::{Chain, Divergence};
+use std::fs;
+
+/// Executes `sheth.wasm` on the output of `client package`, which lists the pre-state root, the
+/// expected post-state root, and the block data of each block, or on a Scout YAML file. Each block
+/// is executed on top of the post-state root of the previous one.
+fn main() {
+    let matches = App::new("sheth-runner")
+        .version("0.0.1")
+        .about("Executes an execution environment with local eth2 host functions")
+        .arg(
+            Arg::with_name("input")
+                .required(true)
+                .help("file containing the blocks along with their state roots, or Scout YAML"),
+        )
+        .arg(
+            Arg::with_name("wasm")
+                .long("wasm")
+                .takes_value(true)
+                .default_value("target/wasm32-unknown-unknown/release/sheth.wasm")
+                .help("path to the compiled execution environment, overriding the Scout scripts"),
+        )
+        .arg(Arg::with_name("meter").long("meter").help(
+            "reports the fuel consumed by each block, and by each phase if the module is metered",
+        ))
+        .get_matches();
+
+    let input = String::from_utf8(read(matches.value_of("input").unwrap())).unwrap_or_else(|_| {
+        println!("Input must be hex encoded");
+        std::process::exit(1)
+    });
+
+    let chain = Chain::parse(&input).unwrap_or_else(|e| {
+        println!("Unable to parse input: {:?}", e);
+        std::process::exit(1)
+    });
+
+    // Each execution environment runs its own script, unless one is given explicitly.
+    let scripts = match (matches.occurrences_of("wasm"), chain.scripts.is_empty()) {
+        (0, false) => chain.scripts.clone(),
+        _ => vec![matches.value_of("wasm").unwrap().to_string(); chain.pre_states.len()],
+    };
+
+    let runners: Vec<Runner> = scripts
+        .iter()
+        .map(|path| {
+            Runner::new(&read(path)).unwrap_or_else(|e| {
+                println!("Unable to load module {}: {:?}", path, e);
+                std::process::exit(1)
+            })
+        })
+        .collect();
+
+    let meter = matches.is_present("meter");
+
+    let result = chain.execute(|env, pre_state, data| -> Result<[u8; 32], Error> {
+        let cost = match meter {
+            true => Some(runners[env].meter(pre_state, data)?),
+            false => None,
+        };
+
+        let post_state = match &cost {
+            Some(cost) => cost.post_state,
+            None => runners[env].execute(pre_state, data)?,
+        };
+
+        println!("pre_state_root  => {:?}", hex::encode(pre_state));
+        println!("post_state_root => {:?}", hex::encode(post_state));
+
+        if let Some(cost) = cost {
+            print!("{}", report(&cost));
+        }
+
+        Ok(post_state)
+    });
+
+    match result {
+        Ok(_) => (),
+        Err(Divergence::Failed { block, error }) => {
+            println!("Execution of block {} failed: {:?}", block, error);
+            std::process::exit(1)
+        }
+        Err(Divergence::Mismatch {
+            block, expected, ..
+        }) => {
+            println!("block {} expected => {:?}", block, hex::encode(expected));
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Lists the fuel consumed by the block, and by each of its phases and transactions if known.
+fn report(cost: &Cost) -> String {
+    let mut out = format!("fuel            => {}\n", cost.total);
+
+    let breakdown = match &cost.breakdown {
+        Some(breakdown) => breakdown,
+        None => return out,
+    };
+
+    let transactions: u64 = breakdown.transactions.iter().sum();
+    let count = breakdown.transactions.len() as u64;
+
+    out.push_str(&format!("  decoding      => {}\n", breakdown.decoding));
+    out.push_str(&format!(
+        "  hashing       => {} (pre-state {}, post-state {})\n",
+        breakdown.hashing(),
+        breakdown.pre_state,
+        breakdown.post_state
+    ));
+    out.push_str(&format!(
+        "  transactions  => {} ({} per transaction)\n",
+        transactions,
+        transactions.checked_div(count).unwrap_or_default()
+    ));
+
+    for (i, fuel) in breakdown.transactions.iter().enumerate() {
+        out.push_str(&format!("    {:<11} => {}\n", i, fuel));
+    }
+
+    out.push_str(&format!("  rest          => {}\n", breakdown.rest));
+    out
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| {
+        println!("Unable to read {}: {}", path, e);
+        std::process::exit(1)
+    })
+}
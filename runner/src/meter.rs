@@ -0,0 +1,99 @@
+use sheth::host::Checkpoint;
+
+/// The fuel consumed by a block. Each executed wasm instruction consumes roughly one unit of fuel,
+/// so the numbers are deterministic across machines.
+#[derive(Debug, PartialEq)]
+pub struct Cost {
+    pub post_state: [u8; 32],
+
+    /// Fuel consumed by the whole block
+    pub total: u64,
+
+    /// Only available if the module reports checkpoints, e.g. `sheth` built with `metering`
+    pub breakdown: Option<Breakdown>,
+}
+
+/// The fuel consumed by each phase of a block.
+#[derive(Debug, PartialEq)]
+pub struct Breakdown {
+    /// Copying the block data, deserializing the transactions, and loading the proof
+    pub decoding: u64,
+
+    /// Calculating the pre-state root from the proof
+    pub pre_state: u64,
+
+    /// Processing each transaction, including skipped ones
+    pub transactions: Vec<u64>,
+
+    /// Calculating the post-state root from the proof
+    pub post_state: u64,
+
+    /// Everything after the post-state root, e.g. saving it
+    pub rest: u64,
+}
+
+impl Breakdown {
+    /// Fuel spent on calculating the state roots.
+    pub fn hashing(&self) -> u64 {
+        self.pre_state + self.post_state
+    }
+}
+
+/// Splits the fuel consumed by a block at each of its checkpoints, which are pairs of the value of
+/// a `Checkpoint` and the fuel consumed when it was reached. Returns `None` unless the checkpoints
+/// describe exactly one block.
+pub(crate) fn breakdown(checkpoints: &[(u32, u64)], total: u64) -> Option<Breakdown> {
+    let (kinds, fuel): (Vec<u32>, Vec<u64>) = checkpoints.iter().cloned().unzip();
+
+    match kinds.as_slice() {
+        [first, second, middle @ .., last]
+            if *first == Checkpoint::Decoded as u32
+                && *second == Checkpoint::PreStateRoot as u32
+                && middle
+                    .iter()
+                    .all(|&kind| kind == Checkpoint::Transaction as u32)
+                && *last == Checkpoint::PostStateRoot as u32 => {}
+        _ => return None,
+    };
+
+    let last = fuel.len() - 1;
+
+    Some(Breakdown {
+        decoding: fuel[0],
+        pre_state: fuel[1] - fuel[0],
+        transactions: fuel[1..last].windows(2).map(|w| w[1] - w[0]).collect(),
+        post_state: fuel[last] - fuel[last - 1],
+        rest: total - fuel[last],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn breakdown_checkpoints() {
+        let checkpoints = [
+            (Checkpoint::Decoded as u32, 10),
+            (Checkpoint::PreStateRoot as u32, 110),
+            (Checkpoint::Transaction as u32, 130),
+            (Checkpoint::Transaction as u32, 160),
+            (Checkpoint::PostStateRoot as u32, 260),
+        ];
+
+        assert_eq!(
+            breakdown(&checkpoints, 300),
+            Some(Breakdown {
+                decoding: 10,
+                pre_state: 100,
+                transactions: vec![20, 30],
+                post_state: 100,
+                rest: 40,
+            })
+        );
+
+        assert_eq!(breakdown(&checkpoints[..2], 300), None);
+        assert_eq!(breakdown(&checkpoints[1..], 300), None);
+        assert_eq!(breakdown(&[], 300), None);
+    }
+}
@@ -0,0 +1,354 @@
+use serde::Deserialize;
+
+/// A sequence of shard blocks, where each block is executed on top of the state left by the
+/// previous block of the same execution environment.
+#[derive(Debug, PartialEq)]
+pub struct Chain {
+    /// Paths to the compiled execution environments (only set by Scout YAML)
+    pub scripts: Vec<String>,
+
+    /// The state root of each execution environment before the first block
+    pub pre_states: Vec<[u8; 32]>,
+
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    /// Index of the execution environment which executes the block
+    pub env: usize,
+    pub data: Vec<u8>,
+
+    /// The expected state root of the execution environment after the block, if known
+    pub post_state: Option<[u8; 32]>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// The input isn't valid Scout YAML
+    YamlInvalid(String),
+
+    /// A value isn't valid hex, or a root isn't 32 bytes
+    ValueInvalid(String),
+
+    /// The input isn't a sequence of `pre_state post_state data` triples
+    ValuesMissing(usize),
+
+    /// A block refers to an execution environment which doesn't exist
+    EnvUnknown(usize),
+
+    /// The pre-state root of a block doesn't match the post-state root of the previous block
+    RootsDisconnected(usize),
+
+    /// An execution environment without blocks has different pre-state and post-state roots
+    RootsMismatch(usize),
+}
+
+/// The reason a chain stopped before its last block.
+#[derive(Debug, PartialEq)]
+pub enum Divergence<E> {
+    /// The block at the index couldn't be executed
+    Failed { block: usize, error: E },
+
+    /// The block at the index resulted in an unexpected post-state root
+    Mismatch {
+        block: usize,
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+}
+
+#[derive(Deserialize)]
+struct Scout {
+    beacon_state: BeaconState,
+    shard_pre_state: ShardState,
+    shard_blocks: Vec<ShardBlock>,
+    shard_post_state: ShardState,
+}
+
+#[derive(Deserialize)]
+struct BeaconState {
+    execution_scripts: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ShardState {
+    exec_env_states: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ShardBlock {
+    env: usize,
+    data: String,
+}
+
+impl Chain {
+    /// Parses either Scout YAML, or the output of `client package`, which is any number of
+    /// `pre_state post_state data` triples separated by whitespace. The triples start with a hex
+    /// encoded root, which can't be the start of a Scout test case.
+    pub fn parse(input: &str) -> Result<Chain, ParseError> {
+        match input.split_whitespace().next() {
+            Some(token) if decode(token).is_err() => Self::from_yaml(input),
+            _ => Self::from_triples(input),
+        }
+    }
+
+    /// Parses a Scout test case. Scout only lists the post-state roots after the last block, so
+    /// those are the only roots that can be checked.
+    pub fn from_yaml(input: &str) -> Result<Chain, ParseError> {
+        let scout: Scout =
+            serde_yaml::from_str(input).map_err(|e| ParseError::YamlInvalid(e.to_string()))?;
+
+        let pre_states = roots(&scout.shard_pre_state.exec_env_states)?;
+        let post_states = roots(&scout.shard_post_state.exec_env_states)?;
+
+        if pre_states.len() != post_states.len() {
+            return Err(ParseError::YamlInvalid(
+                "number of pre-state and post-state roots differ".to_string(),
+            ));
+        }
+
+        let mut blocks = scout
+            .shard_blocks
+            .iter()
+            .map(|block| {
+                if block.env >= pre_states.len() {
+                    return Err(ParseError::EnvUnknown(block.env));
+                }
+
+                Ok(Block {
+                    env: block.env,
+                    data: decode(&block.data)?,
+                    post_state: None,
+                })
+            })
+            .collect::<Result<Vec<Block>, ParseError>>()?;
+
+        for (env, post_state) in post_states.into_iter().enumerate() {
+            match blocks.iter_mut().rev().find(|block| block.env == env) {
+                Some(last) => last.post_state = Some(post_state),
+                None if pre_states[env] != post_state => {
+                    return Err(ParseError::RootsMismatch(env))
+                }
+                None => (),
+            }
+        }
+
+        Ok(Chain {
+            scripts: scout.beacon_state.execution_scripts,
+            pre_states,
+            blocks,
+        })
+    }
+
+    /// Parses a sequence of `pre_state post_state data` triples for a single execution
+    /// environment, where each pre-state root must match the previous post-state root.
+    pub fn from_triples(input: &str) -> Result<Chain, ParseError> {
+        let values: Vec<&str> = input.split_whitespace().collect();
+
+        if values.is_empty() || !values.chunks_exact(3).remainder().is_empty() {
+            return Err(ParseError::ValuesMissing(values.len()));
+        }
+
+        let mut pre_states = vec![];
+        let mut blocks: Vec<Block> = vec![];
+
+        for (i, triple) in values.chunks(3).enumerate() {
+            let pre_state = root(triple[0])?;
+
+            match blocks.last() {
+                Some(previous) if previous.post_state != Some(pre_state) => {
+                    return Err(ParseError::RootsDisconnected(i))
+                }
+                Some(_) => (),
+                None => pre_states.push(pre_state),
+            }
+
+            blocks.push(Block {
+                env: 0,
+                data: decode(triple[2])?,
+                post_state: Some(root(triple[1])?),
+            });
+        }
+
+        Ok(Chain {
+            scripts: vec![],
+            pre_states,
+            blocks,
+        })
+    }
+
+    /// Executes each block with `execute`, which is given the index of the block's execution
+    /// environment, its current state root, and the block data. Returns the state root of each
+    /// execution environment after the last block, or the first block which diverges.
+    pub fn execute<F, E>(&self, mut execute: F) -> Result<Vec<[u8; 32]>, Divergence<E>>
+    where
+        F: FnMut(usize, [u8; 32], &[u8]) -> Result<[u8; 32], E>,
+    {
+        let mut states = self.pre_states.clone();
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let actual = execute(block.env, states[block.env], &block.data)
+                .map_err(|error| Divergence::Failed { block: i, error })?;
+
+            if let Some(expected) = block.post_state {
+                if actual != expected {
+                    return Err(Divergence::Mismatch {
+                        block: i,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+
+            states[block.env] = actual;
+        }
+
+        Ok(states)
+    }
+}
+
+fn roots(values: &[String]) -> Result<Vec<[u8; 32]>, ParseError> {
+    values.iter().map(|value| root(value)).collect()
+}
+
+fn root(value: &str) -> Result<[u8; 32], ParseError> {
+    let bytes = decode(value)?;
+
+    if bytes.len() != 32 {
+        return Err(ParseError::ValueInvalid(format!(
+            "root must be 32 bytes: {}",
+            value
+        )));
+    }
+
+    let mut root = [0u8; 32];
+    root.copy_from_slice(&bytes);
+    Ok(root)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, ParseError> {
+    let value = value.trim_start_matches("0x");
+    hex::decode(value).map_err(|e| ParseError::ValueInvalid(format!("{}: {}", e, value)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex32(byte: u8) -> String {
+        hex::encode([byte; 32])
+    }
+
+    #[test]
+    fn parse_triples() {
+        let input = format!(
+            "{} {} 00\n{} {} 0101",
+            hex32(1),
+            hex32(2),
+            hex32(2),
+            hex32(3)
+        );
+        let chain = Chain::parse(&input).unwrap();
+
+        assert_eq!(chain.pre_states, vec![[1u8; 32]]);
+        assert_eq!(chain.blocks.len(), 2);
+        assert_eq!(chain.blocks[1].data, vec![1, 1]);
+        assert_eq!(chain.blocks[1].post_state, Some([3u8; 32]));
+
+        let disconnected = format!("{} {} 00 {} {} 00", hex32(1), hex32(2), hex32(4), hex32(5));
+        assert_eq!(
+            Chain::parse(&disconnected),
+            Err(ParseError::RootsDisconnected(1))
+        );
+        assert_eq!(Chain::parse(&hex32(1)), Err(ParseError::ValuesMissing(1)));
+    }
+
+    #[test]
+    fn parse_yaml() {
+        let input = format!(
+            "\
+beacon_state:
+    execution_scripts:
+        - scout/sheth.wasm
+shard_pre_state:
+    exec_env_states:
+        - \"{}\"
+shard_blocks:
+    - env: 0
+      data: \"00\"
+    - env: 0
+      data: \"0101\"
+shard_post_state:
+    exec_env_states:
+        - \"{}\"",
+            hex32(1),
+            hex32(3)
+        );
+        let chain = Chain::parse(&input).unwrap();
+
+        assert_eq!(chain.scripts, vec!["scout/sheth.wasm".to_string()]);
+        assert_eq!(chain.pre_states, vec![[1u8; 32]]);
+        assert_eq!(chain.blocks[0].post_state, None);
+        assert_eq!(chain.blocks[1].post_state, Some([3u8; 32]));
+
+        let unknown = input.replace(
+            "- env: 0\n      data: \"00\"",
+            "- env: 1\n      data: \"00\"",
+        );
+        assert_eq!(Chain::parse(&unknown), Err(ParseError::EnvUnknown(1)));
+
+        // A test case without blocks is still parsed as YAML
+        let empty = input.replace("shard_blocks", "shard_block");
+        assert!(matches!(
+            Chain::parse(&empty),
+            Err(ParseError::YamlInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn execute_reports_first_divergence() {
+        let input = format!(
+            "{} {} 02 {} {} 03 {} {} 04",
+            hex32(1),
+            hex32(2),
+            hex32(2),
+            hex32(3),
+            hex32(3),
+            hex32(9)
+        );
+        let chain = Chain::parse(&input).unwrap();
+
+        // Each block sets every byte of the root to its data, as long as the roots are chained.
+        let mut pre_states = vec![];
+        let result = chain.execute(|_, pre_state, data| -> Result<[u8; 32], ()> {
+            pre_states.push(pre_state);
+            Ok([data[0]; 32])
+        });
+
+        assert_eq!(
+            result,
+            Err(Divergence::Mismatch {
+                block: 2,
+                expected: [9u8; 32],
+                actual: [4u8; 32],
+            })
+        );
+        assert_eq!(pre_states, vec![[1u8; 32], [2u8; 32], [3u8; 32]]);
+
+        let result = chain.execute(|_, _, data| {
+            if data[0] == 3 {
+                Err("trap")
+            } else {
+                Ok([2u8; 32])
+            }
+        });
+        assert_eq!(
+            result,
+            Err(Divergence::Failed {
+                block: 1,
+                error: "trap"
+            })
+        );
+    }
+}
@@ -1,10 +1,40 @@
 use crate::u264::U264;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     SignatureInvalid,
     NonceInvalid,
     BalanceInsufficient,
     StateIncomplete(U264),
     Overflow,
+
+    /// The block data is too short for its transactions, or too large for the input buffer
+    BlobInvalid,
+
+    /// The multiproof's offsets don't describe a tree of its chunks
+    ProofInvalid,
+
+    /// The multiproof's root doesn't match the pre-state root
+    PreStateRootMismatch,
+
+    /// The host doesn't provide the requested data, e.g. the beacon state root
+    HostUnsupported,
+}
+
+impl Error {
+    /// Returns the machine-readable code of the error, which a host receives when a block reverts.
+    /// Codes are never reused, and `0` is left for success.
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::SignatureInvalid => 1,
+            Error::NonceInvalid => 2,
+            Error::BalanceInsufficient => 3,
+            Error::StateIncomplete(_) => 4,
+            Error::Overflow => 5,
+            Error::BlobInvalid => 6,
+            Error::ProofInvalid => 7,
+            Error::PreStateRootMismatch => 8,
+            Error::HostUnsupported => 9,
+        }
+    }
 }
@@ -0,0 +1,306 @@
+use crate::error::Error;
+
+/// Interface to the environment which executes `sheth`, e.g. the `eth2` functions provided by Scout.
+///
+/// The entry point only talks to the environment through this trait, so the code path which runs
+/// in Scout can be driven natively with `MockHost`. Calls which not every environment provides
+/// have a default implementation that returns `Error::HostUnsupported`.
+pub trait Host {
+    /// Returns the state root of the execution environment before the block.
+    fn load_pre_state_root(&self) -> [u8; 32];
+
+    /// Returns the size of the block data in bytes.
+    fn block_data_size(&self) -> usize;
+
+    /// Copies the block data starting at `offset` into `buffer`, filling it entirely.
+    fn block_data_copy(&self, buffer: &mut [u8], offset: usize);
+
+    /// Stores the state root of the execution environment after the block.
+    fn save_post_state_root(&mut self, root: &[u8; 32]);
+
+    /// Rejects the block with the code of its error (see `Error::code`), instead of saving a
+    /// post-state root.
+    fn revert(&mut self, code: u32);
+
+    /// Returns the state root of the beacon chain.
+    fn beacon_state_root(&self) -> Result<[u8; 32], Error> {
+        Err(Error::HostUnsupported)
+    }
+
+    /// Returns the slot of the block.
+    fn slot(&self) -> Result<u64, Error> {
+        Err(Error::HostUnsupported)
+    }
+}
+
+/// How `run` handles a block which can't be applied, e.g. because its proof doesn't match the
+/// pre-state root or one of its transactions overflows a balance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvalidBlock {
+    /// Save the pre-state root as the post-state root, so the block has no effect
+    SavePreState,
+
+    /// Call `Host::revert` with the error's code, and save no post-state root
+    Revert,
+}
+
+/// The policy of `run`, chosen at compile time: `Revert` with the `revert` feature, and
+/// `SavePreState` otherwise.
+pub const INVALID_BLOCK: InvalidBlock = if cfg!(feature = "revert") {
+    InvalidBlock::Revert
+} else {
+    InvalidBlock::SavePreState
+};
+
+/// Points in the execution of a block which a metering host uses to break down its cost. Each
+/// checkpoint is passed to the host as its `u32` value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+pub enum Checkpoint {
+    /// The transactions were deserialized and the proof was loaded
+    Decoded = 0,
+
+    /// The pre-state root was calculated from the proof
+    PreStateRoot = 1,
+
+    /// A transaction was processed (or skipped)
+    Transaction = 2,
+
+    /// The post-state root was calculated from the proof
+    PostStateRoot = 3,
+}
+
+// This is a list of functions that `ewasm` environments support. They provide additional data and
+// functionality to execution environments. Each function is implemented in the host environment.
+#[cfg(feature = "scout")]
+mod native {
+    extern "C" {
+        pub fn eth2_loadPreStateRoot(offset: *const u32);
+        pub fn eth2_blockDataSize() -> u32;
+        pub fn eth2_blockDataCopy(outputOfset: *const u32, offset: u32, length: u32);
+        pub fn eth2_savePostStateRoot(offset: *const u32);
+
+        // Only provided by metering hosts, such as the runner
+        #[cfg(feature = "metering")]
+        pub fn sheth_checkpoint(checkpoint: u32);
+
+        // Only provided by hosts which support reverts, such as the runner
+        #[cfg(feature = "revert")]
+        pub fn sheth_revert(code: u32);
+    }
+}
+
+/// Tells the host that execution reached `checkpoint`. Use the `checkpoint!` macro instead, which
+/// compiles away without the `metering` feature.
+#[cfg(feature = "metering")]
+pub fn checkpoint(checkpoint: Checkpoint) {
+    unsafe { native::sheth_checkpoint(checkpoint as u32) }
+}
+
+/// The host functions of an `ewasm` environment, such as Scout.
+#[cfg(feature = "scout")]
+pub struct Eth2Host;
+
+#[cfg(feature = "scout")]
+impl Host for Eth2Host {
+    fn load_pre_state_root(&self) -> [u8; 32] {
+        let mut root = [0u8; 32];
+        unsafe { native::eth2_loadPreStateRoot(root.as_mut_ptr() as *const u32) }
+        root
+    }
+
+    fn block_data_size(&self) -> usize {
+        unsafe { native::eth2_blockDataSize() as usize }
+    }
+
+    fn block_data_copy(&self, buffer: &mut [u8], offset: usize) {
+        unsafe {
+            native::eth2_blockDataCopy(
+                buffer.as_mut_ptr() as *const u32,
+                offset as u32,
+                buffer.len() as u32,
+            )
+        }
+    }
+
+    fn save_post_state_root(&mut self, root: &[u8; 32]) {
+        unsafe { native::eth2_savePostStateRoot(root.as_ptr() as *const u32) }
+    }
+
+    fn revert(&mut self, code: u32) {
+        // Scout doesn't provide `sheth_revert`, so it's only imported with the `revert` feature
+        #[cfg(feature = "revert")]
+        unsafe {
+            native::sheth_revert(code)
+        }
+
+        // Otherwise the block keeps the pre-state root, as with `InvalidBlock::SavePreState`
+        #[cfg(not(feature = "revert"))]
+        {
+            let _ = code;
+            let root = self.load_pre_state_root();
+            self.save_post_state_root(&root);
+        }
+    }
+}
+
+/// A native host which serves a single block from memory.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct MockHost {
+    pub pre_state: [u8; 32],
+    pub block_data: Vec<u8>,
+
+    /// The root saved by the execution environment, if any
+    pub post_state: Option<[u8; 32]>,
+
+    /// The error code the execution environment reverted with, if any
+    pub reverted: Option<u32>,
+
+    /// The beacon state root served to the execution environment, if any
+    pub beacon_state_root: Option<[u8; 32]>,
+
+    /// The slot served to the execution environment, if any
+    pub slot: Option<u64>,
+}
+
+#[cfg(feature = "std")]
+impl MockHost {
+    pub fn new(pre_state: [u8; 32], block_data: Vec<u8>) -> Self {
+        MockHost {
+            pre_state,
+            block_data,
+            post_state: None,
+            reverted: None,
+            beacon_state_root: None,
+            slot: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Host for MockHost {
+    fn load_pre_state_root(&self) -> [u8; 32] {
+        self.pre_state
+    }
+
+    fn block_data_size(&self) -> usize {
+        self.block_data.len()
+    }
+
+    fn block_data_copy(&self, buffer: &mut [u8], offset: usize) {
+        buffer.copy_from_slice(&self.block_data[offset..offset + buffer.len()]);
+    }
+
+    fn save_post_state_root(&mut self, root: &[u8; 32]) {
+        self.post_state = Some(*root);
+    }
+
+    fn revert(&mut self, code: u32) {
+        self.reverted = Some(code);
+    }
+
+    fn beacon_state_root(&self) -> Result<[u8; 32], Error> {
+        self.beacon_state_root.ok_or(Error::HostUnsupported)
+    }
+
+    fn slot(&self) -> Result<u64, Error> {
+        self.slot.ok_or(Error::HostUnsupported)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::address::Address;
+    use crate::{run, run_with_policy};
+    use composer::blob::{generate_with_roots, generate_with_workload, process};
+    use composer::workload::{Invalid, Workload};
+
+    #[test]
+    fn run_with_mock_host() {
+        let (blob, pre_state, post_state) = generate_with_roots(2, 2, 256).unwrap();
+        let mut host = MockHost::new(pre_state, blob.to_bytes());
+
+        assert_eq!(run(&mut host), Ok(()));
+        assert_eq!(host.post_state, Some(post_state));
+        assert_eq!(host.reverted, None);
+    }
+
+    #[test]
+    fn unsupported_host_calls() {
+        let mut host = MockHost::new([0u8; 32], vec![]);
+        assert_eq!(host.beacon_state_root(), Err(Error::HostUnsupported));
+        assert_eq!(host.slot(), Err(Error::HostUnsupported));
+
+        host.beacon_state_root = Some([1u8; 32]);
+        host.slot = Some(7);
+        assert_eq!(host.beacon_state_root(), Ok([1u8; 32]));
+        assert_eq!(host.slot(), Ok(7));
+    }
+
+    /// Returns invalid blocks, along with their pre-state roots and the error which rejects them.
+    fn invalid_blocks() -> Vec<([u8; 32], Vec<u8>, Error)> {
+        let (blob, pre_state, _) = generate_with_roots(2, 2, 256).unwrap();
+        let blob = blob.to_bytes();
+
+        // A block whose transfer overflows the recipient's balance
+        let workload = Workload {
+            invalid_rate: 1.0,
+            invalid: vec![Invalid::Overflow],
+            ..Workload::default()
+        };
+        let overflow = generate_with_workload(2, 1, 256, &workload).unwrap();
+        let (overflow_pre_state, _) = process(&overflow, 256);
+
+        // A block whose transfer is sent to an account which isn't in the proof
+        let mut unknown = blob.clone();
+        unknown[4..36].copy_from_slice(&[0xab; 32]);
+
+        vec![
+            (overflow_pre_state, overflow.to_bytes(), Error::Overflow),
+            (
+                pre_state,
+                unknown,
+                Error::StateIncomplete(Address::from([0xab; 32]).into()),
+            ),
+            ([0u8; 32], blob.clone(), Error::PreStateRootMismatch),
+            (
+                pre_state,
+                blob[..blob.len() - 1].to_vec(),
+                Error::ProofInvalid,
+            ),
+            (pre_state, blob[..100].to_vec(), Error::BlobInvalid),
+            (pre_state, vec![0u8; 50000], Error::BlobInvalid),
+        ]
+    }
+
+    #[test]
+    fn save_pre_state_of_invalid_block() {
+        for (pre_state, data, error) in invalid_blocks() {
+            let mut host = MockHost::new(pre_state, data);
+
+            assert_eq!(
+                run_with_policy(&mut host, InvalidBlock::SavePreState),
+                Err(error)
+            );
+            assert_eq!(host.post_state, Some(pre_state));
+            assert_eq!(host.reverted, None);
+        }
+    }
+
+    #[test]
+    fn revert_invalid_block() {
+        for (pre_state, data, error) in invalid_blocks() {
+            let mut host = MockHost::new(pre_state, data);
+
+            assert_eq!(
+                run_with_policy(&mut host, InvalidBlock::Revert),
+                Err(error.clone())
+            );
+            assert_eq!(host.post_state, None);
+            assert_eq!(host.reverted, Some(error.code()));
+        }
+    }
+}
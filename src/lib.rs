@@ -3,23 +3,47 @@
 extern crate alloc;
 extern crate qimalloc;
 
+/// Records an event in the execution trace. Without the `trace` feature, the event isn't even
+/// evaluated, so tracing compiles away completely.
+macro_rules! trace {
+    ($event:expr) => {
+        #[cfg(feature = "trace")]
+        $crate::trace::record($event);
+    };
+}
+
+/// Tells a metering host that execution reached a checkpoint, so that it can break down the cost
+/// of the block. Without the `metering` feature, this compiles away completely.
+macro_rules! checkpoint {
+    ($checkpoint:expr) => {
+        #[cfg(feature = "metering")]
+        $crate::host::checkpoint($checkpoint);
+    };
+}
+
 pub mod account;
 pub mod address;
 pub mod bls;
+#[cfg(feature = "std")]
+pub mod chain;
 pub mod error;
 pub mod hash;
+pub mod host;
 pub mod process;
 pub mod state;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub mod transaction;
 pub mod u264;
 
+use crate::error::Error;
+use crate::host::{Host, InvalidBlock};
 use crate::process::process_transactions;
 use crate::transaction::{Transaction, Transfer};
 
 #[cfg(feature = "scout")]
 use alloc::vec::Vec;
 use arrayref::array_ref;
-use imp::Imp;
 
 // A global memory allocator is provided as part of the Rust standard library. When a crate is
 // compiled using `no_std` and dynamically allocates memory, it must specify an allocator it wishes
@@ -29,78 +53,202 @@ use imp::Imp;
 #[global_allocator]
 static ALLOC: qimalloc::QIMalloc = qimalloc::QIMalloc::INIT;
 
-// This is a list of functions that `ewasm` environments support. They provide additional data and
-// functionality to execution environments. Each function is implemented in the host environment.
-#[cfg(feature = "scout")]
-mod native {
-    extern "C" {
-        pub fn eth2_loadPreStateRoot(offset: *const u32);
-        pub fn eth2_blockDataSize() -> u32;
-        pub fn eth2_blockDataCopy(outputOfset: *const u32, offset: u32, length: u32);
-        pub fn eth2_savePostStateRoot(offset: *const u32);
-    }
-}
-
 #[cfg(feature = "scout")]
 #[no_mangle]
 pub extern "C" fn main() {
-    let input_size = unsafe { native::eth2_blockDataSize() as usize };
+    // The host has already been told about an invalid block, so its error isn't needed
+    let _ = run(&mut host::Eth2Host);
+}
 
-    // Copy input into buffer (buffer fixed at 42kb for now)
-    let mut input = [0u8; 42000];
-    unsafe {
-        native::eth2_blockDataCopy(input.as_mut_ptr() as *const u32, 0, input_size as u32);
-    }
+/// Executes the block provided by `host` on top of its pre-state root, and saves the post-state
+/// root with the host. An invalid block is handled according to `host::INVALID_BLOCK`, and its
+/// error is returned.
+pub fn run<H: Host>(host: &mut H) -> Result<(), Error> {
+    run_with_policy(host, host::INVALID_BLOCK)
+}
 
+/// Executes the block provided by `host` like `run`, but handles an invalid block according to
+/// `policy` rather than the policy chosen at compile time.
+pub fn run_with_policy<H: Host>(host: &mut H, policy: InvalidBlock) -> Result<(), Error> {
     // Get pre-state-root
-    let mut pre_state_root = [0u8; 32];
-    unsafe { native::eth2_loadPreStateRoot(pre_state_root.as_mut_ptr() as *const u32) }
+    let pre_state_root = host.load_pre_state_root();
 
-    // Process input data
-    let post_root = process_data_blob(&mut input, &pre_state_root);
+    match execute(host, &pre_state_root) {
+        Ok(post_root) => {
+            // Return post state
+            host.save_post_state_root(&post_root);
+            Ok(())
+        }
+        Err(error) => {
+            match policy {
+                InvalidBlock::SavePreState => host.save_post_state_root(&pre_state_root),
+                InvalidBlock::Revert => host.revert(error.code()),
+            }
+
+            Err(error)
+        }
+    }
+}
+
+/// Buffer for the block data (fixed at 42kb for now). `Imp` reads the proof's offsets as `u64`s,
+/// and the proof follows the 4 byte transaction count and the 176 byte transactions, so the block
+/// data is copied to 4 bytes past an 8 byte boundary.
+#[repr(align(8))]
+struct Buffer([u8; 4 + 42000]);
 
-    // Return post state
-    unsafe { native::eth2_savePostStateRoot(post_root.as_ptr() as *const u32) }
+/// Copies the block data out of `host`, and processes it on top of `pre_state_root`.
+fn execute<H: Host>(host: &H, pre_state_root: &[u8; 32]) -> Result<[u8; 32], Error> {
+    let mut buffer = Buffer([0u8; 4 + 42000]);
+    let input = buffer.0[4..]
+        .get_mut(..host.block_data_size())
+        .ok_or(Error::BlobInvalid)?;
+    host.block_data_copy(input, 0);
+
+    // Process input data
+    process_data_blob(input, pre_state_root)
 }
 
-pub fn process_data_blob(blob: &mut [u8], pre_state_root: &[u8; 32]) -> [u8; 32] {
+/// Processes the transactions of `blob` on top of its multiproof, and returns the post-state root.
+/// Returns an error instead of panicking if the blob is malformed, if the proof doesn't match
+/// `pre_state_root`, or if a transaction can't be applied.
+pub fn process_data_blob(blob: &mut [u8], pre_state_root: &[u8; 32]) -> Result<[u8; 32], Error> {
     // Deserialize transactions from byte array. Although this is essentially copying all the
     // transactions, it appears to not have a massive cost. We can optimize later.
-    let tx_count = u32::from_le_bytes(*array_ref!(blob, 0, 4)) as usize;
-    let transactions = deserialize_transactions(&blob, tx_count);
+    let tx_count = blob
+        .get(0..4)
+        .map(|bytes| u32::from_le_bytes(*array_ref!(bytes, 0, 4)) as usize)
+        .ok_or(Error::BlobInvalid)?;
+    let transactions = deserialize_transactions(blob, tx_count)?;
 
     // Load multi-merkle proof
-    let mut mem = Imp::new(&mut blob[(4 + tx_count * 176)..], 259);
+    let proof = &mut blob[(4 + tx_count * 176)..];
+
+    trace!(trace::Event::BlobDecoded {
+        transactions: tx_count,
+        proof: proof.len(),
+    });
+
+    let mut buffer = Vec::new();
+    let mut mem = state::imp::load(proof, &mut buffer, 259)?;
+    checkpoint!(host::Checkpoint::Decoded);
 
     // Verify pre_state_root
     let pre_root = mem.root();
-    assert_eq!(pre_state_root, &pre_root);
+    checkpoint!(host::Checkpoint::PreStateRoot);
 
-    // Proccess all transactions (only transfers for now)
-    assert_eq!(process_transactions(&mut mem, &transactions), Ok(()));
+    trace!(trace::Event::PreStateRoot {
+        expected: *pre_state_root,
+        actual: pre_root,
+    });
 
-    mem.root()
-}
+    if pre_state_root != &pre_root {
+        return Err(Error::PreStateRootMismatch);
+    }
+
+    // Proccess all transactions (only transfers for now)
+    process_transactions(&mut mem, &transactions)?;
 
-pub fn deserialize_transactions(data: &[u8], tx_count: usize) -> Vec<Transaction> {
-    unsafe {
-        let mut ret = Vec::<Transaction>::new();
+    let post_root = mem.root();
+    checkpoint!(host::Checkpoint::PostStateRoot);
+    trace!(trace::Event::PostStateRoot { root: post_root });
 
-        for i in (0..4 + (tx_count * 176)).skip(4).step_by(176) {
-            let mut buf = [0u8; 176];
-            buf.copy_from_slice(&data[i..(i + 176)]);
+    Ok(post_root)
+}
 
-            let tx = Transaction::Transfer(Transfer {
+/// Deserializes the `tx_count` transactions which follow the transaction count at the start of
+/// `data`. Returns `Error::BlobInvalid` if `data` is too short to contain them.
+pub fn deserialize_transactions(data: &[u8], tx_count: usize) -> Result<Vec<Transaction>, Error> {
+    let data = tx_count
+        .checked_mul(176)
+        .and_then(|size| size.checked_add(4))
+        .and_then(|end| data.get(4..end))
+        .ok_or(Error::BlobInvalid)?;
+
+    let transactions = data
+        .chunks_exact(176)
+        .map(|buf| {
+            Transaction::Transfer(Transfer {
                 to: (*array_ref![buf, 0, 32]).into(),
                 from: (*array_ref![buf, 32, 32]).into(),
                 nonce: u64::from_le_bytes(*array_ref![buf, 64, 8]),
                 amount: u64::from_le_bytes(*array_ref![buf, 72, 8]),
                 signature: *array_ref![buf, 80, 96],
-            });
+            })
+        })
+        .collect();
+
+    Ok(transactions)
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::address::Address;
+    use composer::blob::generate_with_roots;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn deserialize_truncated_transactions() {
+        let (blob, _, _) = generate_with_roots(2, 3, 256).unwrap();
+        let blob = blob.to_bytes();
+
+        assert_eq!(deserialize_transactions(&blob, 3).map(|t| t.len()), Ok(3));
+        assert_eq!(
+            deserialize_transactions(&blob[..4], 0).map(|t| t.len()),
+            Ok(0)
+        );
+        assert!(deserialize_transactions(&blob[..4 + 3 * 176 - 1], 3).is_err());
+        assert!(deserialize_transactions(&blob[..3], 0).is_err());
+        assert!(deserialize_transactions(&blob, u32::MAX as usize).is_err());
+        assert!(deserialize_transactions(&blob, usize::MAX).is_err());
+    }
 
-            ret.push(tx);
+    #[test]
+    fn process_malformed_blobs() {
+        let (blob, pre_state, post_state) = generate_with_roots(2, 3, 256).unwrap();
+        let blob = blob.to_bytes();
+
+        assert_eq!(
+            process_data_blob(&mut blob.clone(), &pre_state),
+            Ok(post_state)
+        );
+        assert_eq!(
+            process_data_blob(&mut blob.clone(), &[0u8; 32]),
+            Err(Error::PreStateRootMismatch)
+        );
+
+        for len in 0..blob.len() {
+            assert!(
+                process_data_blob(&mut blob[..len].to_vec(), &pre_state).is_err(),
+                "{} bytes",
+                len
+            );
         }
 
-        ret
+        // Transactions which touch an account that isn't in the proof
+        for field in &[0, 32] {
+            let mut unknown = blob.clone();
+            unknown[4 + field..4 + field + 32].copy_from_slice(&[0xab; 32]);
+
+            assert_eq!(
+                process_data_blob(&mut unknown, &pre_state),
+                Err(Error::StateIncomplete(Address::from([0xab; 32]).into()))
+            );
+        }
+
+        // Corrupted blobs may still be valid (e.g. if only a signature changed), but they must
+        // never panic
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let mut corrupted = blob.clone();
+            for _ in 0..rng.gen_range(1, 4) {
+                let i = rng.gen_range(0, corrupted.len());
+                corrupted[i] = rng.gen();
+            }
+
+            let _ = process_data_blob(&mut corrupted, &pre_state);
+        }
     }
 }
@@ -1,24 +1,146 @@
-use arrayref::array_ref;
-use sheth::process_data_blob;
-use std::fs;
+use sheth::chain::{Chain, Divergence};
+use sheth::error::Error;
+use sheth::host::MockHost;
+use std::io::{self, Read};
+use std::{env, fs};
 
 /// The `main` function is the entry point when `sheth` is compiled as an executable binary.
 /// Testing `sheth` in this manner is usually better than running through `Scout`, because your
 /// local system will print coherent stack traces where as the WebAssembly interpreter will just
 /// notify that a `Trap` has been detected.
+///
+/// Each argument is a test case, which is either the output of `client package` or a Scout YAML
+/// file. When no paths are given (or the path is `-`), the test case is read from stdin. Every
+/// test case is executed, and the process exits with 1 if any of them fails.
+///
+/// Blocks are executed by `sheth::run` with a `MockHost`, so invalid blocks are handled like in
+/// wasm: by default they keep their pre-state root, and with the `revert` feature they fail.
+///
+/// When built with the `trace` feature, `--trace` (or `--trace=json`) prints the events recorded
+/// while executing each block as text (or as a JSON array).
 pub fn main() {
-    let input = fs::read_to_string("blob").expect("File to exist");
+    let (options, mut paths): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
 
-    let args: Vec<&str> = input.split_whitespace().collect();
-    let pre_state_root = hex::decode(args[0]).unwrap();
-    let post_state_root = hex::decode(args[1]).unwrap();
-    let mut input = hex::decode(args[2]).unwrap();
+    let trace = match options.iter().map(String::as_str).collect::<Vec<&str>>()[..] {
+        [] => Trace::Off,
+        ["--trace"] | ["--trace=text"] => Trace::Text,
+        ["--trace=json"] => Trace::Json,
+        _ => {
+            println!("Usage: binsheth [--trace[=text|json]] [paths...]");
+            std::process::exit(1)
+        }
+    };
 
-    // Process input data
-    let post_root = process_data_blob(&mut input, array_ref![pre_state_root, 0, 32]);
+    if trace != Trace::Off && cfg!(not(feature = "trace")) {
+        println!("Tracing requires building binsheth with the `trace` feature");
+        std::process::exit(1)
+    }
 
-    assert_eq!(post_root, *array_ref![post_state_root, 0, 32]);
+    if paths.is_empty() {
+        paths.push("-".to_string());
+    }
 
-    println!("pre_state_root  => {:?}", hex::encode(pre_state_root));
-    println!("post_state_root => {:?}", hex::encode(post_root));
+    let mut failed = 0;
+
+    for path in &paths {
+        println!("{}", name(path));
+
+        match run(path, trace) {
+            Ok(blocks) => println!("ok ({} blocks)\n", blocks),
+            Err(e) => {
+                println!("FAILED: {}\n", e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "test cases: {} passed; {} failed",
+        paths.len() - failed,
+        failed
+    );
+
+    if failed > 0 {
+        std::process::exit(1)
+    }
+}
+
+fn name(path: &str) -> &str {
+    match path {
+        "-" => "<stdin>",
+        path => path,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Trace {
+    Off,
+    Text,
+    Json,
+}
+
+/// Prints the events recorded since the last call, and clears them.
+#[cfg(feature = "trace")]
+fn print_trace(format: Trace) {
+    let events = sheth::trace::take();
+
+    match format {
+        Trace::Off => (),
+        Trace::Text => events.iter().for_each(|event| println!("  {}", event)),
+        Trace::Json => println!("{}", sheth::trace::to_json(&events)),
+    }
+}
+
+#[cfg(not(feature = "trace"))]
+fn print_trace(_: Trace) {}
+
+/// Executes each block of the test case at `path`, and returns the number of blocks executed.
+fn run(path: &str, trace: Trace) -> Result<usize, String> {
+    let input = match path {
+        "-" => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .map(|_| input)
+                .map_err(|e| format!("unable to read stdin: {}", e))
+        }
+        path => fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e)),
+    }?;
+
+    let chain = Chain::parse(&input).map_err(|e| format!("unable to parse: {:?}", e))?;
+
+    let result = chain.execute(|_, pre_state_root, data| -> Result<[u8; 32], Error> {
+        let mut host = MockHost::new(pre_state_root, data.to_vec());
+        let result = sheth::run(&mut host);
+
+        // The events are printed even if the block is invalid, since they lead up to the error
+        print_trace(trace);
+
+        if let Err(e) = &result {
+            println!("invalid block   => {:?} (code {})", e, e.code());
+        }
+
+        // Without a post-state root, the block reverted
+        let post_root = host.post_state.ok_or_else(|| result.unwrap_err())?;
+
+        println!("pre_state_root  => {:?}", hex::encode(pre_state_root));
+        println!("post_state_root => {:?}", hex::encode(post_root));
+
+        Ok(post_root)
+    });
+
+    match result {
+        Ok(_) => Ok(chain.blocks.len()),
+        Err(Divergence::Failed { block, error }) => {
+            Err(format!("block {} failed: {:?}", block, error))
+        }
+        Err(Divergence::Mismatch {
+            block, expected, ..
+        }) => Err(format!(
+            "block {} expected => {:?}",
+            block,
+            hex::encode(expected)
+        )),
+    }
 }
@@ -7,8 +7,22 @@ pub fn process_transactions<'a, T: State>(
     transactions: &[Transaction],
 ) -> Result<(), Error> {
     for tx in transactions {
-        if let Err(_) = tx.verify(db) {
-            continue;
+        let verified = tx.verify(db);
+
+        trace!(crate::trace::Event::TransactionVerified {
+            from: tx.from(),
+            nonce: tx.nonce(),
+            result: verified.clone(),
+        });
+
+        match verified {
+            Ok(()) => (),
+            // A sender which isn't in the proof can't be verified, so the block is rejected
+            Err(Error::StateIncomplete(address)) => return Err(Error::StateIncomplete(address)),
+            Err(_) => {
+                checkpoint!(crate::host::Checkpoint::Transaction);
+                continue;
+            }
         }
 
         db.inc_nonce(tx.from())?;
@@ -18,6 +32,8 @@ pub fn process_transactions<'a, T: State>(
             Transaction::Deposit(_) => unimplemented!(),
             Transaction::Withdrawal(_) => unimplemented!(),
         }
+
+        checkpoint!(crate::host::Checkpoint::Transaction);
     }
 
     Ok(())
@@ -97,12 +113,12 @@ mod test {
         let post_root = mem.root().unwrap();
 
         assert_eq!(
-            "000000000000000000000000000000000000000000000000babe8b8a8d142623",
+            "5d52c22f4798499dd0c534213fbeef7fed521dc7d10f8d2c2cd935468844b8c0",
             hex::encode(pre_root)
         );
 
         assert_eq!(
-            "0000000000000000000000000000000000000000000000003265323fcea3d6a2",
+            "5dcaf2be2e9f3dcab6fa1913bb882d5d584067d716d1f73f43a472783554d569",
             hex::encode(post_root)
         );
     }
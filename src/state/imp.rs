@@ -3,9 +3,120 @@ use crate::address::Address;
 use crate::error::Error;
 use crate::state::State;
 use crate::u264::U264;
+use alloc::vec;
+use alloc::vec::Vec;
 use arrayref::array_ref;
+use core::convert::TryFrom;
+use core::mem::align_of;
 use imp::Imp;
 
+/// Loads the multiproof `proof` of a tree whose account fields are at depth `height`. `Imp` trusts
+/// the proof, so it is checked first: a truncated proof or malformed offsets would otherwise make
+/// `Imp` index out of bounds.
+///
+/// `Imp` also reads the offsets as `u64`s, so a proof which isn't aligned to 8 bytes is copied to
+/// `buffer`, and the updates aren't written back to `proof`.
+pub fn load<'a>(
+    proof: &'a mut [u8],
+    buffer: &'a mut Vec<u64>,
+    height: usize,
+) -> Result<Imp<'a, U264>, Error> {
+    // The proof starts with the number of chunks `n`, followed by `n - 1` offsets and the chunks
+    let count = proof
+        .get(0..8)
+        .map(|bytes| u64::from_le_bytes(*array_ref![bytes, 0, 8]))
+        .and_then(|count| usize::try_from(count).ok())
+        .filter(|count| *count > 0)
+        .ok_or(Error::ProofInvalid)?;
+
+    match count.checked_mul(40) {
+        Some(size) if size <= proof.len() => (),
+        _ => return Err(Error::ProofInvalid),
+    }
+
+    check_offsets(&proof[8..count * 8])?;
+
+    if proof.as_ptr().align_offset(align_of::<u64>()) == 0 {
+        return Ok(Imp::new(proof, height));
+    }
+
+    buffer.clear();
+    buffer.resize(proof.len() / 8 + 1, 0);
+
+    // Any bytes are valid `u64`s, and the buffer holds at least as many bytes as the proof
+    let aligned =
+        unsafe { core::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, proof.len()) };
+    aligned.copy_from_slice(proof);
+
+    Ok(Imp::new(aligned, height))
+}
+
+/// Returns `Ok` if `offsets` describe a tree in which every node has either zero or two children,
+/// which is what `Imp::root` expects.
+fn check_offsets(offsets: &[u8]) -> Result<(), Error> {
+    let offset = |position: usize| u64::from_le_bytes(*array_ref![offsets, position * 8, 8]);
+
+    // The subtrees left to check, as the position of their first offset and their number of
+    // leaves. A subtree of `n` leaves is described by `n - 1` offsets, so positions never exceed
+    // the offsets of the whole tree.
+    let mut subtrees = vec![(0, offsets.len() / 8 + 1)];
+
+    while let Some((position, size)) = subtrees.pop() {
+        if size == 1 {
+            continue;
+        }
+
+        let left = offset(position);
+        if left == 0 || left >= size as u64 {
+            return Err(Error::ProofInvalid);
+        }
+
+        let left = left as usize;
+        subtrees.push((position + 1, left));
+        subtrees.push((position + left, size - left));
+    }
+
+    Ok(())
+}
+
+/// Returns `Ok` if the account field at `index` (e.g. a nonce or value) is a leaf of the
+/// multiproof. `Imp` doesn't check this during lookups, so reading an account which isn't in the
+/// proof would otherwise return some unrelated node.
+fn ensure_leaf(imp: &Imp<U264>, address: Address, index: U264) -> Result<(), Error> {
+    let offset = |position: u64| -> Option<u64> {
+        let begin = (position * 8) as usize;
+        imp.offsets
+            .get(begin..begin + 8)
+            .map(|bytes| u64::from_le_bytes(*array_ref![bytes, 0, 8]))
+    };
+
+    // The number of leaves in the current subtree
+    let mut size = (imp.offsets.len() / 8 + 1) as u64;
+    let mut position = 0u64;
+
+    // Account fields are one level above the bottom of the tree
+    for i in 1..imp.height {
+        let left = match offset(position) {
+            Some(left) if size > 1 && left < size => left,
+            _ => return Err(Error::StateIncomplete(address.into())),
+        };
+
+        if (index >> (imp.height - i)).low_u32() & 1 == 0 {
+            size = left;
+            position += 1;
+        } else {
+            size -= left;
+            position += left;
+        }
+    }
+
+    if size == 1 {
+        Ok(())
+    } else {
+        Err(Error::StateIncomplete(address.into()))
+    }
+}
+
 impl<'a> State for Imp<'a, U264> {
     fn root(&mut self) -> Result<[u8; 32], Error> {
         Ok(self.root())
@@ -13,23 +124,37 @@ impl<'a> State for Imp<'a, U264> {
 
     fn value(&self, address: Address) -> Result<u64, Error> {
         let index = calc_value_index(address, self.height);
+        ensure_leaf(self, address, index)?;
         let chunk = self.get(index);
-        Ok(u64::from_le_bytes(*array_ref![&chunk, 0, 8]))
+        let value = u64::from_le_bytes(*array_ref![&chunk, 0, 8]);
+
+        trace!(crate::trace::Event::Read { index, value });
+
+        Ok(value)
     }
 
     fn nonce(&self, address: Address) -> Result<u64, Error> {
         let index = calc_nonce_index(address, self.height);
+        ensure_leaf(self, address, index)?;
         let chunk = self.get(index);
-        Ok(u64::from_le_bytes(*array_ref![&chunk, 0, 8]))
+        let nonce = u64::from_le_bytes(*array_ref![&chunk, 0, 8]);
+
+        trace!(crate::trace::Event::Read {
+            index,
+            value: nonce
+        });
+
+        Ok(nonce)
     }
 
     fn add_value(&mut self, address: Address, amount: u64) -> Result<u64, Error> {
         let index = calc_value_index(address, self.height);
+        ensure_leaf(self, address, index)?;
         let chunk = self.get(index);
 
-        let value = u64::from_le_bytes(*array_ref![&chunk, 0, 8]);
+        let old = u64::from_le_bytes(*array_ref![&chunk, 0, 8]);
 
-        let (value, overflow) = value.overflowing_add(amount);
+        let (value, overflow) = old.overflowing_add(amount);
         if overflow {
             return Err(Error::Overflow);
         }
@@ -38,16 +163,23 @@ impl<'a> State for Imp<'a, U264> {
         buf[0..8].copy_from_slice(&value.to_le_bytes());
         self.update(index, buf);
 
+        trace!(crate::trace::Event::Write {
+            index,
+            old,
+            new: value
+        });
+
         Ok(value)
     }
 
     fn sub_value(&mut self, address: Address, amount: u64) -> Result<u64, Error> {
         let index = calc_value_index(address, self.height);
+        ensure_leaf(self, address, index)?;
         let chunk = self.get(index);
 
-        let value = u64::from_le_bytes(*array_ref![chunk, 0, 8]);
+        let old = u64::from_le_bytes(*array_ref![chunk, 0, 8]);
 
-        let (value, overflow) = value.overflowing_sub(amount);
+        let (value, overflow) = old.overflowing_sub(amount);
         if overflow {
             return Err(Error::Overflow);
         }
@@ -56,16 +188,23 @@ impl<'a> State for Imp<'a, U264> {
         buf[0..8].copy_from_slice(&value.to_le_bytes());
         self.update(index, buf);
 
+        trace!(crate::trace::Event::Write {
+            index,
+            old,
+            new: value
+        });
+
         Ok(value)
     }
 
     fn inc_nonce(&mut self, address: Address) -> Result<u64, Error> {
         let index = calc_nonce_index(address, self.height);
+        ensure_leaf(self, address, index)?;
         let chunk = self.get(index);
 
-        let nonce = u64::from_le_bytes(*array_ref![chunk, 0, 8]);
+        let old = u64::from_le_bytes(*array_ref![chunk, 0, 8]);
 
-        let (nonce, overflow) = nonce.overflowing_add(1);
+        let (nonce, overflow) = old.overflowing_add(1);
         if overflow {
             return Err(Error::Overflow);
         }
@@ -74,6 +213,12 @@ impl<'a> State for Imp<'a, U264> {
         buf[0..8].copy_from_slice(&nonce.to_le_bytes());
         self.update(index, buf);
 
+        trace!(crate::trace::Event::Write {
+            index,
+            old,
+            new: nonce
+        });
+
         Ok(nonce)
     }
 }
@@ -142,4 +287,63 @@ mod test {
         assert_eq!(mem.inc_nonce(0.into()), Ok(2));
         assert_eq!(mem.get((9 << 1).into()), h256(2));
     }
+
+    #[test]
+    fn load_proof() {
+        let mut proof = get_proof();
+        let root = Imp::<U264>::new(&mut proof.clone(), 4).root();
+        assert_eq!(
+            load(&mut proof, &mut vec![], 4)
+                .map(|mut mem| mem.root())
+                .ok(),
+            Some(root)
+        );
+
+        // Unaligned proofs are copied
+        let mut unaligned = vec![0u8; proof.len() + 1];
+        unaligned[1..].copy_from_slice(&proof);
+        assert_eq!(
+            load(&mut unaligned[1..], &mut vec![], 4)
+                .map(|mut mem| mem.root())
+                .ok(),
+            Some(root)
+        );
+
+        // Truncated proofs
+        assert!(load(&mut [], &mut vec![], 4).is_err());
+        assert!(load(&mut proof[..7], &mut vec![], 4).is_err());
+        let len = proof.len();
+        assert!(load(&mut proof[..len - 1], &mut vec![], 4).is_err());
+
+        // Invalid counts
+        let mut invalid = proof.clone();
+        invalid[0] = 0;
+        assert!(load(&mut invalid, &mut vec![], 4).is_err());
+        invalid[7] = 0xff;
+        assert!(load(&mut invalid, &mut vec![], 4).is_err());
+
+        // Invalid offsets, i.e. an empty left subtree and a left subtree with every leaf
+        for left in &[0, 6] {
+            let mut invalid = proof.clone();
+            invalid[8] = *left;
+            assert!(load(&mut invalid, &mut vec![], 4).is_err());
+        }
+
+        let mut invalid = proof.clone();
+        invalid[16] = 5;
+        assert!(load(&mut invalid, &mut vec![], 4).is_err());
+    }
+
+    #[test]
+    fn address_not_in_proof() {
+        let mut proof = get_proof();
+        let mut mem = Imp::new(&mut proof, 4);
+
+        assert_eq!(mem.value(1.into()), Err(Error::StateIncomplete(1.into())));
+        assert_eq!(mem.nonce(1.into()), Err(Error::StateIncomplete(1.into())));
+        assert_eq!(
+            mem.add_value(1.into(), 1),
+            Err(Error::StateIncomplete(1.into()))
+        );
+    }
 }
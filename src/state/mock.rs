@@ -1,87 +1,316 @@
 use crate::account::Account;
 use crate::address::Address;
 use crate::error::Error;
-use crate::hash::H256;
+use crate::hash::{hash, zh, H256};
 use crate::state::State;
+use arrayref::array_ref;
 use bigint::U256;
-use std::collections::hash_map::DefaultHasher;
 use std::collections::BTreeMap;
-use std::hash::{Hash, Hasher};
 
+/// A state which holds every account in memory. It calculates the same sparse merkle root as a
+/// multiproof loaded into `Imp`, and returns the same errors.
 pub struct MockState {
     accounts: BTreeMap<Address, Account>,
+    height: usize,
 }
 
 impl MockState {
+    /// Returns a state with the accounts in a tree of height 256, which is the tree of `sheth`.
     pub fn new(accounts: BTreeMap<Address, Account>) -> MockState {
-        MockState { accounts }
+        MockState::with_height(accounts, 256)
+    }
+
+    /// Returns a state with the accounts in a tree of height `height`, which must have room for
+    /// each address.
+    pub fn with_height(accounts: BTreeMap<Address, Account>, height: usize) -> MockState {
+        MockState { accounts, height }
+    }
+
+    fn account(&self, address: Address) -> Result<&Account, Error> {
+        self.accounts
+            .get(&address)
+            .ok_or_else(|| Error::StateIncomplete(address.into()))
+    }
+
+    /// Applies `f` to the account at `address`, and only stores the result if it succeeds.
+    fn update<F>(&mut self, address: Address, f: F) -> Result<u64, Error>
+    where
+        F: FnOnce(&mut Account) -> Option<u64>,
+    {
+        let mut account = self.account(address)?.clone();
+        let ret = f(&mut account).ok_or(Error::Overflow)?;
+        self.accounts.insert(address, account);
+
+        Ok(ret)
     }
 }
 
 impl State for MockState {
     fn root(&mut self) -> Result<H256, Error> {
-        let mut s = DefaultHasher::new();
-        self.accounts.hash(&mut s);
-        let hash = U256::from(s.finish());
-        Ok(hash.into())
+        // The account roots are the leaves of the tree. Each level is hashed into the level above
+        // it, where missing siblings are the roots of empty subtrees.
+        let mut level: BTreeMap<U256, H256> = self
+            .accounts
+            .iter()
+            .map(|(address, account)| {
+                let address: [u8; 32] = (*address).into();
+                (U256::from(address), account_root(account))
+            })
+            .collect();
+
+        for depth in 0..self.height {
+            let zero = zero_hash(depth);
+            let mut parents = BTreeMap::new();
+
+            for (index, node) in level.iter() {
+                let sibling = level.get(&(*index ^ U256::one())).unwrap_or(&zero);
+
+                let parent = match index.low_u64() & 1 {
+                    0 => hash_pair(node, sibling),
+                    _ => hash_pair(sibling, node),
+                };
+
+                parents.insert(*index >> 1, parent);
+            }
+
+            level = parents;
+        }
+
+        Ok(level
+            .get(&U256::zero())
+            .cloned()
+            .unwrap_or_else(|| zero_hash(self.height)))
     }
 
     fn value(&self, address: Address) -> Result<u64, Error> {
-        let value = self
-            .accounts
-            .get(&address)
-            .ok_or(Error::StateIncomplete(address.into()))?
-            .value;
-
-        Ok(value)
+        Ok(self.account(address)?.value)
     }
 
     fn nonce(&self, address: Address) -> Result<u64, Error> {
-        let nonce = self
-            .accounts
-            .get(&address)
-            .ok_or(Error::StateIncomplete(address.into()))?
-            .nonce;
-
-        Ok(nonce)
+        Ok(self.account(address)?.nonce)
     }
 
     fn add_value(&mut self, address: Address, amount: u64) -> Result<u64, Error> {
-        let mut account = self
-            .accounts
-            .get(&address)
-            .ok_or(Error::StateIncomplete(address.into()))?
-            .clone();
+        self.update(address, |account| {
+            account.value = account.value.checked_add(amount)?;
+            Some(account.value)
+        })
+    }
 
-        account.value += amount;
-        self.accounts.insert(address, account.clone());
+    fn sub_value(&mut self, address: Address, amount: u64) -> Result<u64, Error> {
+        self.update(address, |account| {
+            account.value = account.value.checked_sub(amount)?;
+            Some(account.value)
+        })
+    }
 
-        Ok(account.value)
+    fn inc_nonce(&mut self, address: Address) -> Result<u64, Error> {
+        self.update(address, |account| {
+            account.nonce = account.nonce.checked_add(1)?;
+            Some(account.nonce)
+        })
     }
+}
 
-    fn sub_value(&mut self, address: Address, amount: u64) -> Result<u64, Error> {
-        let mut account = self
-            .accounts
-            .get(&address)
-            .ok_or(Error::StateIncomplete(address.into()))?
-            .clone();
+/// Returns the root of an account's tree, whose leaves are the two chunks of the public key, the
+/// nonce, the value, and a zero chunk of padding.
+fn account_root(account: &Account) -> H256 {
+    let mut buf = [0u8; 128];
+    buf[0..48].copy_from_slice(&account.pubkey.as_bytes());
+    buf[64..72].copy_from_slice(&account.nonce.to_le_bytes());
+    buf[96..104].copy_from_slice(&account.value.to_le_bytes());
+
+    let pubkey = hash_pair(array_ref![buf, 0, 32], array_ref![buf, 32, 32]);
+    let pubkey_nonce = hash_pair(&pubkey, array_ref![buf, 64, 32]);
+    let value_padding = hash_pair(array_ref![buf, 96, 32], &[0u8; 32]);
 
-        account.value -= amount;
-        self.accounts.insert(address, account.clone());
+    hash_pair(&pubkey_nonce, &value_padding)
+}
+
+/// Returns the root of an empty subtree whose leaves are `depth` levels below it.
+fn zero_hash(depth: usize) -> H256 {
+    let mut buf = [0u8; 64];
+    zh(depth, &mut buf);
+    *array_ref![buf, 0, 32]
+}
+
+fn hash_pair(left: &H256, right: &H256) -> H256 {
+    let mut buf = [0u8; 64];
+    buf[0..32].copy_from_slice(left);
+    buf[32..64].copy_from_slice(right);
+    hash(&mut buf);
+    *array_ref![buf, 0, 32]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bls::PublicKey;
+    use crate::process::process_transactions;
+    use crate::transaction::{Transaction, Transfer};
+    use crate::u264::U264;
+    use composer::accounts::random_accounts_with_seed;
+    use composer::proof::uncompressed::generate;
+    use composer::smt::SparseMerkleTree;
+    use imp::Imp;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
-        Ok(account.value)
+    #[test]
+    fn empty_root() {
+        let mut state = MockState::with_height(BTreeMap::new(), 8);
+        assert_eq!(state.root(), Ok(zero_hash(8)));
+
+        // A zero account is indistinguishable from a missing one
+        let mut accounts = BTreeMap::new();
+        accounts.insert(3.into(), Account::zero());
+        let mut state = MockState::with_height(accounts, 8);
+        assert_eq!(state.root(), Ok(zero_hash(8)));
     }
 
-    fn inc_nonce(&mut self, address: Address) -> Result<u64, Error> {
-        let mut account = self
-            .accounts
-            .get(&address)
-            .ok_or(Error::StateIncomplete(address.into()))?
-            .clone();
+    #[test]
+    fn errors_leave_state_untouched() {
+        let mut accounts = BTreeMap::new();
+        accounts.insert(
+            0.into(),
+            Account {
+                value: u64::MAX,
+                ..Account::zero()
+            },
+        );
+
+        let mut state = MockState::with_height(accounts, 4);
+        let root = state.root();
+
+        assert_eq!(state.add_value(0.into(), 1), Err(Error::Overflow));
+        assert_eq!(
+            state.sub_value(1.into(), 1),
+            Err(Error::StateIncomplete(1.into()))
+        );
+        assert_eq!(state.sub_value(0.into(), u64::MAX), Ok(0));
+        assert_eq!(state.sub_value(0.into(), 1), Err(Error::Overflow));
+        assert_eq!(state.add_value(0.into(), u64::MAX), Ok(u64::MAX));
+        assert_eq!(state.root(), root);
+    }
+
+    /// Returns a random transfer between the accounts, which may be invalid in any way that `Imp`
+    /// detects: the nonce may be wrong, the amount may overdraw the sender or overflow the
+    /// recipient, and either address may be missing from the state.
+    fn random_transfer(rng: &mut StdRng, state: &MockState, addresses: &[Address]) -> Transaction {
+        let pick = |rng: &mut StdRng| match rng.gen_bool(0.1) {
+            true => U256::from(rng.gen_range(0, 1u64 << state.height)).into(),
+            false => addresses[rng.gen_range(0, addresses.len())],
+        };
+
+        let from = pick(rng);
+        let to = pick(rng);
+
+        let nonce = match state.nonce(from) {
+            Ok(nonce) if rng.gen_bool(0.9) => nonce,
+            _ => rng.gen(),
+        };
+
+        let amount = match (rng.gen_range(0, 4), state.value(from)) {
+            (0, _) => u64::MAX,
+            (1, Ok(value)) => value,
+            (2, Ok(value)) => rng.gen_range(0, value.saturating_add(1)),
+            _ => rng.gen::<u64>() >> rng.gen_range(0, 64),
+        };
+
+        Transaction::Transfer(Transfer {
+            to,
+            from,
+            nonce,
+            amount,
+            signature: [0u8; 96],
+        })
+    }
+
+    #[test]
+    fn matches_imp() {
+        let mut results = vec![];
+
+        // Whether each `StateIncomplete` was caused by the sender, rather than the recipient
+        let mut missing_senders = vec![];
+
+        for seed in 0..32 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let height = rng.gen_range(2, 17);
+
+            let mut accounts =
+                random_accounts_with_seed(rng.gen_range(1, 5), height, seed).unwrap();
+
+            // Some balances are close to overflowing
+            for account in accounts.iter_mut() {
+                if rng.gen_bool(0.3) {
+                    account.1.value = u64::MAX - rng.gen_range(0, 100);
+                }
+            }
+
+            let addresses: Vec<Address> = accounts.iter().map(|a| a.0.into()).collect();
+            let expected = SparseMerkleTree::from_accounts(accounts.clone(), height)
+                .unwrap()
+                .root();
+
+            let mut proof = generate(accounts.clone(), height).to_bytes();
+            let mut imp = Imp::<U264>::new(&mut proof, height + 3);
+
+            // The composer builds accounts of its own copy of `sheth`
+            let mut mock = MockState::with_height(
+                accounts
+                    .into_iter()
+                    .map(|a| {
+                        let account = Account {
+                            pubkey: PublicKey::new(a.1.pubkey.as_bytes()),
+                            nonce: a.1.nonce,
+                            value: a.1.value,
+                        };
+
+                        (a.0.into(), account)
+                    })
+                    .collect(),
+                height,
+            );
+
+            assert_eq!(mock.root(), Ok(expected));
+            assert_eq!(State::root(&mut imp), Ok(expected));
+
+            for i in 0..20 {
+                let tx = random_transfer(&mut rng, &mock, &addresses);
+                let result = process_transactions(&mut mock, std::slice::from_ref(&tx));
+
+                assert_eq!(
+                    process_transactions(&mut imp, std::slice::from_ref(&tx)),
+                    result,
+                    "seed {}, transaction {}: {:?}",
+                    seed,
+                    i,
+                    tx
+                );
+                assert_eq!(
+                    State::root(&mut imp),
+                    mock.root(),
+                    "seed {}, transaction {}: {:?}",
+                    seed,
+                    i,
+                    tx
+                );
+
+                if let (Err(Error::StateIncomplete(address)), Transaction::Transfer(t)) =
+                    (&result, &tx)
+                {
+                    missing_senders.push(*address == t.from.into());
+                }
 
-        account.nonce += 1;
-        self.accounts.insert(address, account.clone());
+                results.push(result);
+            }
+        }
 
-        Ok(account.nonce)
+        // Make sure every outcome was compared, including accounts missing from the proof on
+        // either side of a transfer
+        assert!(results.contains(&Ok(())));
+        assert!(results.contains(&Err(Error::Overflow)));
+        assert!(missing_senders.contains(&true));
+        assert!(missing_senders.contains(&false));
     }
 }
@@ -0,0 +1,222 @@
+use crate::address::Address;
+use crate::error::Error;
+use crate::hash::H256;
+use crate::u264::U264;
+use bigint::U512;
+use serde::{Serialize, Serializer};
+use std::cell::RefCell;
+use std::fmt;
+
+thread_local! {
+    static LOG: RefCell<Vec<Event>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Something that happened during the execution of a blob.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The blob was split into its transactions and the bytes of its proof
+    BlobDecoded { transactions: usize, proof: usize },
+
+    /// The root calculated from the proof was compared with the expected pre-state root
+    PreStateRoot {
+        #[serde(serialize_with = "serialize_hex")]
+        expected: H256,
+        #[serde(serialize_with = "serialize_hex")]
+        actual: H256,
+    },
+
+    /// A transaction was verified, and is skipped unless the result is `Ok`
+    TransactionVerified {
+        #[serde(serialize_with = "serialize_address")]
+        from: Address,
+        nonce: u64,
+        #[serde(serialize_with = "serialize_result")]
+        result: Result<(), Error>,
+    },
+
+    /// A value was read from the chunk of the proof at the general index
+    Read {
+        #[serde(serialize_with = "serialize_index")]
+        index: U264,
+        value: u64,
+    },
+
+    /// A value in the chunk of the proof at the general index was replaced
+    Write {
+        #[serde(serialize_with = "serialize_index")]
+        index: U264,
+        old: u64,
+        new: u64,
+    },
+
+    /// The root calculated from the proof after the transactions
+    PostStateRoot {
+        #[serde(serialize_with = "serialize_hex")]
+        root: H256,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::BlobDecoded {
+                transactions,
+                proof,
+            } => write!(
+                f,
+                "decoded {} transactions and {} bytes of proof",
+                transactions, proof
+            ),
+            Event::PreStateRoot { expected, actual } if expected == actual => {
+                write!(f, "pre-state root 0x{} matches", ::hex::encode(actual))
+            }
+            Event::PreStateRoot { expected, actual } => write!(
+                f,
+                "pre-state root 0x{} doesn't match 0x{}",
+                ::hex::encode(actual),
+                ::hex::encode(expected)
+            ),
+            Event::TransactionVerified {
+                from,
+                nonce,
+                result,
+            } => write!(
+                f,
+                "transaction from 0x{} with nonce {}: {:?}",
+                ::hex::encode(<[u8; 32]>::from(*from)),
+                nonce,
+                result
+            ),
+            Event::Read { index, value } => write!(f, "read {} at {}", value, to_u512(index)),
+            Event::Write { index, old, new } => {
+                write!(f, "write {} -> {} at {}", old, new, to_u512(index))
+            }
+            Event::PostStateRoot { root } => {
+                write!(f, "post-state root 0x{}", ::hex::encode(root))
+            }
+        }
+    }
+}
+
+/// Appends an event to the log of the current thread. Use the `trace!` macro instead, which
+/// compiles away without the `trace` feature.
+pub fn record(event: Event) {
+    LOG.with(|log| log.borrow_mut().push(event))
+}
+
+/// Returns the events recorded by the current thread, and clears its log.
+pub fn take() -> Vec<Event> {
+    LOG.with(|log| log.replace(Vec::new()))
+}
+
+/// Serializes events as a JSON array.
+pub fn to_json(events: &[Event]) -> String {
+    serde_json::to_string(events).expect("Events to be serializable")
+}
+
+fn to_u512(index: &U264) -> U512 {
+    U512::from_little_endian(index.as_le_bytes())
+}
+
+fn serialize_hex<S: Serializer>(bytes: &H256, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("0x{}", ::hex::encode(bytes)))
+}
+
+fn serialize_address<S: Serializer>(address: &Address, s: S) -> Result<S::Ok, S::Error> {
+    serialize_hex(&(*address).into(), s)
+}
+
+// General indexes don't fit into a JSON number, so they are written as decimal strings.
+fn serialize_index<S: Serializer>(index: &U264, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&to_u512(index).to_string())
+}
+
+fn serialize_result<S: Serializer>(result: &Result<(), Error>, s: S) -> Result<S::Ok, S::Error> {
+    match result {
+        Ok(()) => s.serialize_str("Ok"),
+        Err(e) => s.serialize_str(&format!("{:?}", e)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::process_data_blob;
+    use composer::blob::{generate_with_roots, Blob};
+
+    fn execute(blob: &Blob, pre_state: [u8; 32]) -> Vec<Event> {
+        take();
+        process_data_blob(&mut blob.to_bytes(), &pre_state).unwrap();
+        take()
+    }
+
+    #[test]
+    fn trace_transfer() {
+        let (blob, pre_state, post_state) = generate_with_roots(2, 1, 256).unwrap();
+        let events = execute(&blob, pre_state);
+
+        assert_eq!(
+            events[0],
+            Event::BlobDecoded {
+                transactions: 1,
+                proof: blob.to_bytes().len() - 180,
+            }
+        );
+        assert_eq!(
+            events[1],
+            Event::PreStateRoot {
+                expected: pre_state,
+                actual: pre_state,
+            }
+        );
+
+        // The nonce is read while verifying, then the nonce and both values are updated
+        assert!(matches!(events[2], Event::Read { .. }));
+        assert!(matches!(
+            events[3],
+            Event::TransactionVerified { result: Ok(()), .. }
+        ));
+        assert_eq!(
+            events[4..7]
+                .iter()
+                .filter(|e| matches!(e, Event::Write { .. }))
+                .count(),
+            3
+        );
+        assert_eq!(events[7], Event::PostStateRoot { root: post_state });
+        assert_eq!(events.len(), 8);
+
+        assert!(take().is_empty());
+    }
+
+    #[test]
+    fn trace_to_json() {
+        let events = vec![
+            Event::Write {
+                index: U264::one() << 260,
+                old: 1,
+                new: 2,
+            },
+            Event::TransactionVerified {
+                from: 1.into(),
+                nonce: 0,
+                result: Err(Error::NonceInvalid),
+            },
+        ];
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&events)).unwrap();
+
+        assert_eq!(json[0]["event"], "write");
+        assert_eq!(json[0]["index"], (U512::one() << 260).to_string());
+        assert_eq!(json[1]["event"], "transaction_verified");
+        assert_eq!(json[1]["result"], "NonceInvalid");
+        assert_eq!(
+            events[1].to_string(),
+            format!(
+                "transaction from 0x{:0>64} with nonce 0: Err(NonceInvalid)",
+                1
+            )
+        );
+    }
+}